@@ -0,0 +1,33 @@
+//-----------------------------------------------------------------------------
+//------------------ Bulk vs One-at-a-Time Entity Creation --------------------
+//-----------------------------------------------------------------------------
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ecs_it::world::World;
+
+fn create_entities(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create_entities");
+
+    for n in [100, 10_000] {
+        group.bench_with_input(BenchmarkId::new("bulk", n), &n, |b, &n| {
+            b.iter(|| {
+                let w = World::new();
+                black_box(w.create_entities(n));
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("loop", n), &n, |b, &n| {
+            b.iter(|| {
+                let w = World::new();
+                for _ in 0..n {
+                    black_box(w.create_entity());
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, create_entities);
+criterion_main!(benches);
@@ -0,0 +1,49 @@
+//-----------------------------------------------------------------------------
+//---------------------- Read Guard Acquisition Cost --------------------------
+//-----------------------------------------------------------------------------
+
+//To compare before and after a change to the Accessor, run
+//`cargo bench --bench read_guards -- --save-baseline before` on the old tree,
+//then `cargo bench --bench read_guards -- --baseline before` on the new one.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ecs_it::{world::World, Component};
+
+const READS: usize = 10_000;
+
+struct Pos;
+impl Component for Pos {}
+
+fn read_guards(c: &mut Criterion) {
+    let w = World::new();
+    w.register_component::<Pos>();
+
+    let mut group = c.benchmark_group("read_guards");
+
+    group.bench_function("uncontended", |b| {
+        b.iter(|| {
+            for _ in 0..READS {
+                drop(w.req_read_guard::<Pos>());
+            }
+        })
+    });
+
+    group.bench_function("4_threads", |b| {
+        b.iter(|| {
+            std::thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..READS / 4 {
+                            drop(w.req_read_guard::<Pos>());
+                        }
+                    });
+                }
+            })
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, read_guards);
+criterion_main!(benches);
@@ -0,0 +1,46 @@
+//-----------------------------------------------------------------------------
+//------------- Concurrent Spawning: Entities Mutex vs Lock-Free --------------
+//-----------------------------------------------------------------------------
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use ecs_it::{world::World, Entity};
+
+const PER_THREAD: usize = 1_000;
+
+///Spawns PER_THREAD Entities from each of threads threads at once.
+fn spawn_from(threads: usize, spawn: fn(&World) -> Entity) {
+    let w = World::new();
+
+    std::thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..PER_THREAD {
+                    black_box(spawn(&w));
+                }
+            });
+        }
+    });
+}
+
+fn spawn_concurrently(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spawn_concurrently");
+
+    for threads in [1, 4, 8] {
+        group.bench_with_input(
+            BenchmarkId::new("create_entity", threads),
+            &threads,
+            |b, &threads| b.iter(|| spawn_from(threads, World::create_entity)),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("reserve_entity", threads),
+            &threads,
+            |b, &threads| b.iter(|| spawn_from(threads, |w| w.reserve_entity().unwrap())),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, spawn_concurrently);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Provides `#[derive(Component)]` for the ecs_it crate; use it via the
+//! re-export `ecs_it::Component` rather than depending on this crate directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::{parse_macro_input, parse_quote, spanned::Spanned, Data, DeriveInput, Fields};
+
+///Emits `impl ecs_it::Component for T {}`.
+///
+///Every field is checked for `Send + Sync` individually, so a field that can't
+///be shared between threads is reported at that field rather than at the
+///derive, and every type parameter is bounded by `Send + Sync + 'static`.
+#[proc_macro_derive(Component)]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let mut ast = parse_macro_input!(input as DeriveInput);
+
+    for param in ast.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::core::marker::Send));
+        param.bounds.push(parse_quote!(::core::marker::Sync));
+        param.bounds.push(parse_quote!('static));
+    }
+
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let field_checks = field_checks(&ast.data);
+
+    let expanded = quote! {
+        impl #impl_generics ::ecs_it::Component for #name #ty_generics #where_clause {}
+
+        const _: () = {
+            fn __component_field_is_send_sync<T: ?Sized + ::core::marker::Send + ::core::marker::Sync>() {}
+
+            fn __check_component_fields #impl_generics () #where_clause {
+                #field_checks
+            }
+        };
+    };
+
+    expanded.into()
+}
+
+fn field_checks(data: &Data) -> TokenStream2 {
+    let fields: Vec<&Fields> = match data {
+        Data::Struct(data) => vec![&data.fields],
+        Data::Enum(data) => data.variants.iter().map(|v| &v.fields).collect(),
+        Data::Union(data) => {
+            return syn::Error::new(
+                data.union_token.span(),
+                "Component cannot be derived for unions",
+            )
+            .to_compile_error();
+        }
+    };
+
+    fields
+        .into_iter()
+        .flat_map(|fields| fields.iter())
+        .map(|field| {
+            let ty = &field.ty;
+            quote_spanned! {ty.span()=>
+                __component_field_is_send_sync::<#ty>();
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,99 @@
+//-----------------------------------------------------------------------------
+//------------------- Spawning Entities With Many Components ------------------
+//-----------------------------------------------------------------------------
+
+use std::any::TypeId;
+
+use super::{join::acquisition_order, storage::MutableStorageGuard, world::World, Component, Entity};
+
+///A tuple of 1 to 8 distinct Component types to spawn an Entity with; see
+///World::spawn() and World::spawn_batch_bundle().
+pub trait Bundle: Sized {
+    #[doc(hidden)]
+    fn spawn_all(world: &World, bundles: Vec<Self>) -> Vec<Entity>;
+}
+
+impl World {
+    ///Creates an Entity with every Component in bundle, a tuple of 1 to 8
+    ///distinct Component types, and returns it.
+    ///
+    ///Write guards over every Storage involved are acquired first, in TypeId
+    ///order like a Join's, and held until every Component is in; so no other
+    ///thread ever sees the Entity with only some of them. on_insert() hooks
+    ///fire while all of those guards are held.
+    ///
+    ///# Example
+    ///```
+    /// use ecs_it::*;
+    ///
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// impl Component for Pos {}
+    /// impl Component for Vel {}
+    ///
+    /// let world = world::World::new();
+    /// world.register_component::<Pos>();
+    /// world.register_component::<Vel>();
+    ///
+    /// let ent = world.spawn((Pos(0), Vel(3)));
+    /// assert_eq!(world.req_read_guard::<Vel>().get(&ent).unwrap().0, 3);
+    ///```
+    /// ## Panics
+    /// Panics if any of the Component types is unregistered or appears twice,
+    /// in which case no Entity is created, or if the World's Entity limit is
+    /// reached.
+    pub fn spawn<B: Bundle>(&self, bundle: B) -> Entity {
+        B::spawn_all(self, vec![bundle])[0]
+    }
+
+    ///Like spawn(), but creates one Entity per bundle yielded by iter, under
+    ///a single acquisition of the guards; the multi-Component counterpart of
+    ///spawn_batch(). Returns the new Entities in the same order as iter.
+    /// ## Panics
+    /// As for spawn(); also panics if the Entity limit would be exceeded.
+    pub fn spawn_batch_bundle<B, I>(&self, iter: I) -> Vec<Entity>
+    where
+        B: Bundle,
+        I: IntoIterator<Item = B>,
+    {
+        B::spawn_all(self, iter.into_iter().collect())
+    }
+}
+
+macro_rules! impl_bundle {
+    ($n:literal; $($t:ident $c:ident $g:ident $idx:tt),+) => {
+        impl<$($t: Component),+> Bundle for ($($t,)+) {
+            fn spawn_all(world: &World, bundles: Vec<Self>) -> Vec<Entity> {
+                let hooks = ($(world.hooks::<$t>(),)+);
+
+                //Every guard is taken before any Entity is created, so an
+                //unregistered type panics without leaving Entities behind.
+                $(let mut $g: Option<MutableStorageGuard<$t>> = None;)+
+                let order = acquisition_order::<$n>([$((TypeId::of::<$t>(), true, $idx),)+]);
+                for idx in order {
+                    match idx {
+                        $($idx => $g = Some(world.req_write_guard::<$t>()),)+
+                        _ => unreachable!(),
+                    }
+                }
+                $(let mut $g = $g.expect("Bundle guard not acquired");)+
+
+                let ents = world.create_entities(bundles.len());
+                for (ent, ($($c,)+)) in ents.iter().copied().zip(bundles) {
+                    $(hooks.$idx.insert(&mut $g, ent, $c);)+
+                }
+
+                ents
+            }
+        }
+    };
+}
+
+impl_bundle!(1; A a g0 0);
+impl_bundle!(2; A a g0 0, B b g1 1);
+impl_bundle!(3; A a g0 0, B b g1 1, C c g2 2);
+impl_bundle!(4; A a g0 0, B b g1 1, C c g2 2, D d g3 3);
+impl_bundle!(5; A a g0 0, B b g1 1, C c g2 2, D d g3 3, E e g4 4);
+impl_bundle!(6; A a g0 0, B b g1 1, C c g2 2, D d g3 3, E e g4 4, F f g5 5);
+impl_bundle!(7; A a g0 0, B b g1 1, C c g2 2, D d g3 3, E e g4 4, F f g5 5, G g g6 6);
+impl_bundle!(8; A a g0 0, B b g1 1, C c g2 2, D d g3 3, E e g4 4, F f g5 5, G g g6 6, H h g7 7);
@@ -5,17 +5,70 @@
 //------------------------------- ECS Entities --------------------------------
 //-----------------------------------------------------------------------------
 
-use std::collections::{hash_set::Iter, HashSet};
+use std::{
+    collections::{hash_set::Iter, HashSet, VecDeque},
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+///A generational index which represents an in-diegesis 'thing' in the game.
+///
+///When an Entity dies its index is eventually reused, but with its generation
+///bumped, so a stale copy of the dead Entity never matches the new one; it
+///simply finds no Components. See World::is_alive().
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Entity { index, generation }
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
 
-use super::Entity;
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+///Which dead Entity ID is reused first; see WorldConfig.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecyclePolicy {
+    ///The most recently freed ID is reused first, so IDs come back in
+    ///reverse order of when maintain_ecs() freed them.
+    #[default]
+    Lifo,
+    ///The longest-freed ID is reused first, maximizing the time between
+    ///reuses of any one index.
+    Fifo,
+}
 
 ///Internal; generating, controlling, and  holding unique Entity IDs.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Entities {
     //Invariant:
-    //The intersection of active and dead entities is the null set.
-    num_entities: usize,
+    //The intersection of any two of active, unmaintained, and dead entities
+    //is the null set.
+    num_entities: u32,
     active_entities: HashSet<Entity>,
-    dead_entities: Vec<Entity>,
+    unmaintained_entities: Vec<Entity>, //removed, but Components not yet cleared
+    dead_entities: VecDeque<Entity>,    //removed and cleared; free for recycling
+    //A World setting, not state, so it isn't saved; see World::replace_entities().
+    #[cfg_attr(feature = "serde", serde(skip))]
+    recycling: RecyclePolicy,
 }
 
 impl Entities {
@@ -23,14 +76,24 @@ impl Entities {
         Entities {
             num_entities: 0,
             active_entities: HashSet::new(),
-            dead_entities: Vec::new(),
+            unmaintained_entities: Vec::new(),
+            dead_entities: VecDeque::new(),
+            recycling: RecyclePolicy::default(),
         }
     }
 
-    pub(crate) fn new_entity_id(&mut self) -> Entity {
-        let entity_id = self.get_next_id();
+    pub(crate) fn recycling(&self) -> RecyclePolicy {
+        self.recycling
+    }
+
+    pub(crate) fn set_recycling(&mut self, recycling: RecyclePolicy) {
+        self.recycling = recycling;
+    }
+
+    ///fresh_indices is World's high-water mark; see mint_index().
+    pub(crate) fn new_entity_id(&mut self, fresh_indices: &AtomicU32) -> Entity {
+        let entity_id = self.get_next_id(fresh_indices);
         self.active_entities.insert(entity_id);
-        self.num_entities += 1;
 
         entity_id
     }
@@ -38,42 +101,115 @@ impl Entities {
     ///This returns a boolean corresponding to whether the entity existed or not.
     ///If it existed, it was removed and this will return true, else false.
     ///Attempting to remove an Entity that doesn't exist won't panic.
+    ///
+    ///The removed Entity is not recycled until World::maintain_ecs() has
+    ///cleared its Components and handed it back via recycle().
     pub(crate) fn rm_entity(&mut self, ent: Entity) -> bool {
-        //Panics if ent doesn't exist.
         if let Some(entity_to_rm) = self.active_entities.take(&ent) {
-            self.dead_entities.push(entity_to_rm);
+            self.unmaintained_entities.push(entity_to_rm);
             return true;
         }
 
         false
     }
 
+    ///Like rm_entity(), but the caller takes over clearing the Entity's
+    ///Components and recycling it, rather than World::maintain_ecs().
+    pub(crate) fn rm_entity_unqueued(&mut self, ent: Entity) -> bool {
+        self.active_entities.remove(&ent)
+    }
+
+    ///Hands every Entity removed since the last call over to the caller, who
+    ///is responsible for clearing its Components then calling recycle().
+    pub(crate) fn take_unmaintained(&mut self) -> Vec<Entity> {
+        std::mem::take(&mut self.unmaintained_entities)
+    }
+
+    pub(crate) fn recycle(&mut self, cleared: Vec<Entity>) {
+        self.dead_entities.extend(cleared);
+    }
+
     pub(crate) fn living_iter(&self) -> Iter<'_, Entity> {
         self.active_entities.iter()
     }
 
-    pub(crate) fn dead_iter(&self) -> std::slice::Iter<'_, Entity> {
-        self.dead_entities.iter()
+    pub(crate) fn living_count(&self) -> usize {
+        self.active_entities.len()
+    }
+
+    ///Removed Entities, whether or not their Components have been cleared.
+    pub(crate) fn dead_count(&self) -> usize {
+        self.unmaintained_entities.len() + self.dead_entities.len()
     }
 
+    pub(crate) fn has_unmaintained(&self) -> bool {
+        !self.unmaintained_entities.is_empty()
+    }
+
+    ///Every living Entity, in ascending index order.
     pub(crate) fn vec(&self) -> Vec<Entity> {
-        let mut vec = Vec::with_capacity(self.active_entities.len());
-        let iter = self.active_entities.iter();
+        let mut living: Vec<Entity> = self.living_iter().copied().collect();
+        living.sort_unstable();
+        living
+    }
 
-        for ent in iter {
-            vec.push(ent.clone());
-        }
+    ///Every Entity which may still have Components: the living ones, plus the
+    ///removed ones that World::maintain_ecs() hasn't gotten to yet.
+    pub(crate) fn with_components(&self) -> Vec<Entity> {
+        self.active_entities
+            .iter()
+            .chain(self.unmaintained_entities.iter())
+            .copied()
+            .collect()
+    }
 
-        vec
+    pub(crate) fn is_alive(&self, ent: &Entity) -> bool {
+        self.active_entities.contains(ent)
     }
 
-    fn get_next_id(&mut self) -> Entity {
-        let mut new_id: usize = self.num_entities;
+    ///Number of indices ever handed out and recorded here.
+    pub(crate) fn num_entities(&self) -> u32 {
+        self.num_entities
+    }
 
-        if let Some(id) = self.dead_entities.pop() {
-            new_id = id;
+    ///Records every index below minted, which World::reserve_entity() may
+    ///have handed out without locking this list, as a living Entity.
+    pub(crate) fn flush_reserved(&mut self, minted: u32) {
+        for index in self.num_entities..minted {
+            self.active_entities.insert(Entity::new(index, 0));
         }
 
-        new_id
+        self.num_entities = self.num_entities.max(minted);
     }
+
+    ///Recycles a dead index if one is available, bumping its generation, else
+    ///mints a brand-new index. Only minting grows num_entities, so fresh
+    ///indices are always contiguous. Which dead index is recycled first
+    ///depends on the RecyclePolicy.
+    fn get_next_id(&mut self, fresh_indices: &AtomicU32) -> Entity {
+        let recycled = match self.recycling {
+            RecyclePolicy::Lifo => self.dead_entities.pop_back(),
+            RecyclePolicy::Fifo => self.dead_entities.pop_front(),
+        };
+
+        if let Some(dead) = recycled {
+            return Entity::new(dead.index, dead.generation.wrapping_add(1));
+        }
+
+        let index = mint_index(fresh_indices);
+        //Catches up on any indices reserved meanwhile, but not this one,
+        //which new_entity_id() records itself.
+        self.flush_reserved(index);
+        self.num_entities = index + 1;
+
+        Entity::new(index, 0)
+    }
+}
+
+///Takes the next never-used index from World's high-water mark. Lock-free, so
+///World::reserve_entity() can call it without holding the Entities list.
+pub(crate) fn mint_index(fresh_indices: &AtomicU32) -> u32 {
+    fresh_indices
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |next| next.checked_add(1))
+        .expect("exceeded the maximum number of Entity indices")
 }
@@ -0,0 +1,78 @@
+//-----------------------------------------------------------------------------
+//--------------------------------- ECS Errors --------------------------------
+//-----------------------------------------------------------------------------
+
+use std::{error::Error, fmt};
+
+use super::Entity;
+
+///Returned by the non-panicking counterparts of the World API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ECSError {
+    ///The Component type was never registered with the World.
+    Unregistered { type_name: &'static str },
+    ///The Component type was registered more than once.
+    AlreadyRegistered { type_name: &'static str },
+    ///A guard over the Component type's Storage still exists.
+    Borrowed { type_name: &'static str },
+    ///The Entity was removed, or its index has since been recycled.
+    NotAlive { entity: Entity },
+    ///Creating the Entities would exceed the World's living Entity limit.
+    LimitReached { limit: usize },
+    ///A thread panicked while holding one of the World's internal locks.
+    Poisoned,
+    ///A snapshot names a Component that wasn't registered as serializable.
+    UnknownComponentName { name: String },
+    ///A Component failed to serialize while saving a snapshot.
+    Serialize { type_name: &'static str },
+    ///A Component failed to deserialize while loading a snapshot.
+    Deserialize { type_name: &'static str },
+}
+
+impl fmt::Display for ECSError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ECSError::Unregistered { type_name } => write!(
+                f,
+                "ECSError: {} is not registered; register it before use",
+                type_name
+            ),
+            ECSError::AlreadyRegistered { type_name } => {
+                write!(f, "ECSError: {} is already registered", type_name)
+            }
+            ECSError::Borrowed { type_name } => write!(
+                f,
+                "ECSError: {} is still borrowed; drop its guards first",
+                type_name
+            ),
+            ECSError::NotAlive { entity } => write!(
+                f,
+                "ECSError: entity {} is not alive; was it removed?",
+                entity
+            ),
+            ECSError::LimitReached { limit } => write!(
+                f,
+                "ECSError: the World's limit of {} living entities was reached",
+                limit
+            ),
+            ECSError::Poisoned => write!(
+                f,
+                "ECSError: a World lock was poisoned by a thread that panicked"
+            ),
+            ECSError::UnknownComponentName { name } => write!(
+                f,
+                "ECSError: snapshot names {}, which is not a registered serializable component",
+                name
+            ),
+            ECSError::Serialize { type_name } => {
+                write!(f, "ECSError: a {} failed to serialize", type_name)
+            }
+            ECSError::Deserialize { type_name } => {
+                write!(f, "ECSError: a {} failed to deserialize", type_name)
+            }
+        }
+    }
+}
+
+impl Error for ECSError {}
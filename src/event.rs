@@ -0,0 +1,67 @@
+//-----------------------------------------------------------------------------
+//------------------------ Per-Type Event/Message Queues ----------------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{type_name, TypeId},
+    cell::UnsafeCell,
+    mem,
+    sync::Arc,
+};
+
+use super::{
+    storage::{AccessPriority, Accessor},
+    world::World,
+};
+
+const EVENT_POISON: &str = "events mtx found poisoned in event.rs";
+
+///Events of a single type, waiting to be drained. Only ever accessed under
+///write access, so E need only be Send.
+pub(crate) struct EventQueue<E> {
+    accessor: Accessor,
+    inner: UnsafeCell<Vec<E>>,
+}
+
+unsafe impl<E> Sync for EventQueue<E> where E: Send {}
+
+impl<E> EventQueue<E> {
+    fn with<R>(&self, f: impl FnOnce(&mut Vec<E>) -> R) -> R {
+        self.accessor.init_write_access();
+        //Exclusivity is guaranteed by the Accessor.
+        let ret = f(unsafe { &mut *self.inner.get() });
+        self.accessor.drop_write_access();
+        ret
+    }
+}
+
+impl World {
+    ///Appends an event to the queue for its type, letting one system talk to
+    ///another without either knowing about the other. Events need no
+    ///registration; the queue is created on first send.
+    pub fn send_event<E: 'static + Send>(&self, e: E) {
+        self.event_queue::<E>().with(|queue| queue.push(e));
+    }
+
+    ///Takes every event of type E sent so far, in the order they were sent,
+    ///leaving the queue empty. Returns an empty Vec if none were ever sent.
+    pub fn drain_events<E: 'static + Send>(&self) -> Vec<E> {
+        self.event_queue::<E>().with(mem::take)
+    }
+
+    fn event_queue<E: 'static + Send>(&self) -> Arc<EventQueue<E>> {
+        let mut events = self.events.lock().expect(EVENT_POISON);
+
+        let queue = events.entry(TypeId::of::<E>()).or_insert_with(|| {
+            Arc::new(EventQueue::<E> {
+                accessor: Accessor::new(AccessPriority::default(), type_name::<E>()),
+                inner: UnsafeCell::new(Vec::new()),
+            })
+        });
+
+        Arc::clone(queue)
+            .downcast::<EventQueue<E>>()
+            .expect("event queue stored under the wrong TypeId")
+    }
+}
+
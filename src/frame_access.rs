@@ -0,0 +1,154 @@
+//-----------------------------------------------------------------------------
+//------------------- Frame-Long Bulk Access to Many Storages -----------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+};
+
+use super::{
+    storage::{without_order_check, ImmutableStorageGuard, MutableStorageGuard},
+    world::World,
+    Component, Entity,
+};
+
+///A bundle of StorageGuards acquired once at the start of a frame and held
+///until this struct is dropped at the end of the frame.
+///
+///Obtain one via World::frame_access(), then declare every Storage the frame
+///needs via read::<T>() and write::<T>(). Each declaration acquires its guard
+///immediately (blocking, just like req_read_guard()/req_write_guard()), after
+///which all access through the FrameAccess is lock-free.
+///
+///IMPORTANT: Every declared Storage is serialized for the entire lifetime of
+///this struct; no other thread can write to any declared Storage (or read from
+///any Storage declared via write::<T>()) until the FrameAccess is dropped.
+///This trades away concurrency for minimal per-access overhead, so it is
+///meant for single-threaded hot paths. If several threads build FrameAccesses,
+///declare Storages in the same order on each of them to avoid deadlock.
+///
+///# Example
+///```
+/// use ecs_it::*;
+///
+/// struct Pos(usize);
+/// struct Vel(usize);
+/// impl Component for Pos {}
+/// impl Component for Vel {}
+///
+/// let world = world::World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Vel>();
+///
+/// let ent = world.create_entity();
+/// world.add_component(ent, Pos(0));
+/// world.add_component(ent, Vel(2));
+///
+/// {
+///     let mut frame = world.frame_access().read::<Vel>().write::<Pos>();
+///
+///     let vel = frame.get::<Vel>(&ent).unwrap().0;
+///     frame.get_mut::<Pos>(&ent).unwrap().0 += vel;
+/// } //All guards are released here.
+///
+/// assert_eq!(world.req_read_guard::<Pos>().get(&ent).unwrap().0, 2);
+///```
+pub struct FrameAccess<'w> {
+    world: &'w World,
+    guards: HashMap<TypeId, FrameGuard>,
+}
+
+enum FrameGuard {
+    Read(Box<dyn Any>),  //ImmutableStorageGuard<T>
+    Write(Box<dyn Any>), //MutableStorageGuard<T>
+}
+
+impl<'w> FrameAccess<'w> {
+    pub(crate) fn new(world: &'w World) -> Self {
+        FrameAccess {
+            world,
+            guards: HashMap::new(),
+        }
+    }
+
+    ///Declares read access to the Storage of T for the rest of the frame.
+    ///## Panics
+    ///Panics if T is unregistered or was already declared on this FrameAccess.
+    pub fn read<T: Component>(mut self) -> Self {
+        self.assert_undeclared::<T>();
+        let guard = without_order_check(|| self.world.req_read_guard::<T>());
+        self.guards
+            .insert(TypeId::of::<T>(), FrameGuard::Read(Box::new(guard)));
+        self
+    }
+
+    ///Declares write access to the Storage of T for the rest of the frame.
+    ///## Panics
+    ///Panics if T is unregistered or was already declared on this FrameAccess.
+    pub fn write<T: Component>(mut self) -> Self {
+        self.assert_undeclared::<T>();
+        let guard = without_order_check(|| self.world.req_write_guard::<T>());
+        self.guards
+            .insert(TypeId::of::<T>(), FrameGuard::Write(Box::new(guard)));
+        self
+    }
+
+    pub fn get<T: Component>(&self, e: &Entity) -> Option<&T> {
+        self.raw::<T>().get(e)
+    }
+
+    ///Counts as a change to that one Component for iter_changed_since().
+    ///## Panics
+    ///Panics if T was not declared via write::<T>() on this FrameAccess.
+    pub fn get_mut<T: Component>(&mut self, e: &Entity) -> Option<&mut T> {
+        self.write_guard::<T>().get_mut(e)
+    }
+
+    ///Direct access to the whole Storage of T, which may have been declared
+    ///via either read::<T>() or write::<T>().
+    ///## Panics
+    ///Panics if T was not declared on this FrameAccess.
+    pub fn raw<T: Component>(&self) -> &HashMap<Entity, T> {
+        match self.guards.get(&TypeId::of::<T>()) {
+            Some(FrameGuard::Read(boxed)) => boxed
+                .downcast_ref::<ImmutableStorageGuard<T>>()
+                .expect("FrameAccess guard downcast failed")
+                .raw(),
+            Some(FrameGuard::Write(boxed)) => boxed
+                .downcast_ref::<MutableStorageGuard<T>>()
+                .expect("FrameAccess guard downcast failed")
+                .raw(),
+            None => panic!("Storage was not declared on this FrameAccess"),
+        }
+    }
+
+    ///Direct mutable access to the whole Storage of T. Like
+    ///MutableStorageGuard::raw_mut(), this marks every Component of T as
+    ///changed, so prefer get_mut() for single lookups.
+    ///## Panics
+    ///Panics if T was not declared via write::<T>() on this FrameAccess.
+    pub fn raw_mut<T: Component>(&mut self) -> &mut HashMap<Entity, T> {
+        self.write_guard::<T>().raw_mut()
+    }
+
+    fn write_guard<T: Component>(&mut self) -> &mut MutableStorageGuard<T> {
+        match self.guards.get_mut(&TypeId::of::<T>()) {
+            Some(FrameGuard::Write(boxed)) => boxed
+                .downcast_mut::<MutableStorageGuard<T>>()
+                .expect("FrameAccess guard downcast failed"),
+            Some(FrameGuard::Read(_)) => {
+                panic!("Storage was declared read-only on this FrameAccess")
+            }
+            None => panic!("Storage was not declared on this FrameAccess"),
+        }
+    }
+
+    fn assert_undeclared<T: Component>(&self) {
+        //Requesting a second guard on an already-guarded Storage would block
+        //this thread forever, so fail loudly instead.
+        if self.guards.contains_key(&TypeId::of::<T>()) {
+            panic!("attempted to declare the same Storage twice on one FrameAccess");
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//-----------------------------------------------------------------------------
+//------------------ Callbacks On Component Insertion/Removal -----------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{Any, TypeId},
+    sync::Arc,
+};
+
+use super::{storage::MutableStorageGuard, world::World, Component, Entity};
+
+const HOOK_POISON: &str = "hooks mtx found poisoned in hook.rs";
+
+type Hook<T> = Arc<dyn Fn(Entity, &T) + Send + Sync>;
+
+///Every callback registered for one Component type. World keeps the master
+///copy; each hooked call clones it out, so no lock is held while they run.
+pub(crate) struct Hooks<T> {
+    on_insert: Vec<Hook<T>>,
+    on_remove: Vec<Hook<T>>,
+}
+
+impl<T> Clone for Hooks<T> {
+    fn clone(&self) -> Self {
+        Hooks {
+            on_insert: self.on_insert.clone(),
+            on_remove: self.on_remove.clone(),
+        }
+    }
+}
+
+impl<T> Default for Hooks<T> {
+    fn default() -> Self {
+        Hooks {
+            on_insert: Vec::new(),
+            on_remove: Vec::new(),
+        }
+    }
+}
+
+impl<T: Component> Hooks<T> {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.on_insert.is_empty() && self.on_remove.is_empty()
+    }
+
+    ///MutableStorageGuard::insert(), then fires on_remove for the displaced
+    ///Component, if any, and on_insert for the new one.
+    pub(crate) fn insert(
+        &self,
+        guard: &mut MutableStorageGuard<T>,
+        e: Entity,
+        c: T,
+    ) -> Option<T> {
+        let old = guard.insert(e, c);
+
+        if let Some(old) = &old {
+            self.on_remove.iter().for_each(|f| f(e, old));
+        }
+
+        if let Some(new) = guard.raw().get(&e) {
+            self.on_insert.iter().for_each(|f| f(e, new));
+        }
+
+        old
+    }
+
+    ///MutableStorageGuard::remove(), then fires on_remove if there was a
+    ///Component to remove.
+    pub(crate) fn remove(&self, guard: &mut MutableStorageGuard<T>, e: &Entity) -> Option<T> {
+        let old = guard.remove(e)?;
+        self.on_remove.iter().for_each(|f| f(*e, &old));
+
+        Some(old)
+    }
+}
+
+impl World {
+    ///Registers f to be called whenever a Component of type T is added to an
+    ///Entity, e.g. to keep a spatial index keyed on Position up to date.
+    ///
+    ///Fires from add_component(), add_components(), try_add_component(),
+    ///add_component_lazy(), get_or_insert_default(), and move_component();
+    ///writing through a guard directly doesn't fire it.
+    ///Replacing a Component fires on_remove() hooks for the old one first.
+    ///
+    ///Hooks run right after the insertion, in registration order, while the
+    ///write guard on the Storage of T is still held: the Component is in
+    ///place, but nothing else can see it yet. So f must not request the
+    ///Storage of T, which would deadlock (debug builds panic instead). Other
+    ///Storages and Resources are fine, under the usual lock-order rules.
+    pub fn on_insert<T: Component>(&self, f: impl Fn(Entity, &T) + Send + Sync + 'static) {
+        self.edit_hooks::<T>(|hooks| hooks.on_insert.push(Arc::new(f)));
+    }
+
+    ///Registers f to be called with each Component of type T removed from an
+    ///Entity, after it has left the Storage.
+    ///
+    ///Fires from rm_component(), try_rm_component(), rm_component_by_type(),
+    ///and move_component(), when maintain_ecs() or despawn_now() clears a
+    ///removed Entity's Components (linked ones included), and when a
+    ///Component is replaced via add_component() and friends; writing through
+    ///a guard directly doesn't fire it.
+    ///
+    ///Ordering and deadlock rules are as for on_insert(): f runs while the
+    ///write guard on the Storage of T is held. When fired by maintain_ecs()
+    ///or despawn_now(), f must not register Component types either.
+    pub fn on_remove<T: Component>(&self, f: impl Fn(Entity, &T) + Send + Sync + 'static) {
+        self.edit_hooks::<T>(|hooks| hooks.on_remove.push(Arc::new(f)));
+    }
+
+    ///A copy of every hook registered for T, which may be none.
+    pub(crate) fn hooks<T: Component>(&self) -> Hooks<T> {
+        self.hooks
+            .lock()
+            .expect(HOOK_POISON)
+            .get(&TypeId::of::<T>())
+            .and_then(|hooks| hooks.downcast_ref::<Hooks<T>>())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    ///Drops every hook registered for the type, e.g. on unregistering it.
+    pub(crate) fn forget_hooks(&self, type_id: TypeId) {
+        self.hooks.lock().expect(HOOK_POISON).remove(&type_id);
+    }
+
+    fn edit_hooks<T: Component>(&self, f: impl FnOnce(&mut Hooks<T>)) {
+        let mut hooks = self.hooks.lock().expect(HOOK_POISON);
+
+        let entry = hooks
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Hooks::<T>::default()) as Box<dyn Any + Send + Sync>);
+
+        f(entry
+            .downcast_mut::<Hooks<T>>()
+            .expect("hooks stored under the wrong TypeId"));
+    }
+}
@@ -0,0 +1,443 @@
+//-----------------------------------------------------------------------------
+//----------------------- Joins Over Multiple Storages ------------------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::TypeId,
+    collections::{hash_map::Keys, HashMap},
+    vec,
+};
+
+use super::{
+    storage::{ImmutableStorageGuard, MutableStorageGuard},
+    world::World,
+    Component, Entity,
+};
+
+///Implemented for &T (read access) and &mut T (write access), where T is a
+///Component. Tuples of these are what a Join is generic over.
+pub trait Fetch {
+    type Component: Component;
+    type Guard;
+    type Item<'a>
+    where
+        Self::Guard: 'a;
+
+    fn acquire(world: &World) -> Self::Guard;
+
+    fn try_acquire(world: &World) -> Option<Self::Guard>;
+
+    fn is_write() -> bool;
+
+    fn raw(guard: &Self::Guard) -> &HashMap<Entity, Self::Component>;
+
+    fn fetch<'a>(guard: &'a mut Self::Guard, e: &Entity) -> Option<Self::Item<'a>>;
+}
+
+impl<T: Component> Fetch for &T {
+    type Component = T;
+    type Guard = ImmutableStorageGuard<T>;
+    type Item<'a> = &'a T;
+
+    fn acquire(world: &World) -> Self::Guard {
+        world.req_read_guard::<T>()
+    }
+
+    fn try_acquire(world: &World) -> Option<Self::Guard> {
+        world.try_req_read_guard::<T>()
+    }
+
+    fn is_write() -> bool {
+        false
+    }
+
+    fn raw(guard: &Self::Guard) -> &HashMap<Entity, T> {
+        guard.raw()
+    }
+
+    fn fetch<'a>(guard: &'a mut Self::Guard, e: &Entity) -> Option<&'a T> {
+        guard.get(e)
+    }
+}
+
+impl<T: Component> Fetch for &mut T {
+    type Component = T;
+    type Guard = MutableStorageGuard<T>;
+    type Item<'a> = &'a mut T;
+
+    fn acquire(world: &World) -> Self::Guard {
+        world.req_write_guard::<T>()
+    }
+
+    fn try_acquire(world: &World) -> Option<Self::Guard> {
+        world.try_req_write_guard::<T>()
+    }
+
+    fn is_write() -> bool {
+        true
+    }
+
+    fn raw(guard: &Self::Guard) -> &HashMap<Entity, T> {
+        guard.raw()
+    }
+
+    fn fetch<'a>(guard: &'a mut Self::Guard, e: &Entity) -> Option<&'a mut T> {
+        guard.get_mut(e)
+    }
+}
+
+///Holds a guard for every Storage named in Q, a tuple of 1 to 6 &T or &mut T,
+///until dropped. Build one via the join! macro or Join::new().
+///
+///Guards are always acquired in ascending TypeId order, regardless of the
+///order of Q, so two threads joining over overlapping Storages can't
+///deadlock each other.
+///
+///Only Entities with every Component in Q are visited. The first Component
+///in Q drives the iteration, so put the rarest Component first.
+///
+///Storages are HashMaps, so the order Entities are visited in is unspecified
+///and may differ between runs. Build the Join via join_sorted! or
+///Join::new_sorted() instead where it must be reproducible, e.g. for replays.
+///
+///# Example
+///```
+/// use ecs_it::*;
+///
+/// struct Pos(i32);
+/// struct Vel(i32);
+/// impl Component for Pos {}
+/// impl Component for Vel {}
+///
+/// let world = world::World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Vel>();
+///
+/// let ent = world.create_entity();
+/// world.add_component(ent, Pos(0));
+/// world.add_component(ent, Vel(3));
+/// let _no_vel = world.create_entity();
+///
+/// join!(world, &mut Pos, &Vel).for_each(|_ent, pos, vel| pos.0 += vel.0);
+///
+/// let joined = join!(world, &Pos, &Vel);
+/// let positions: Vec<i32> = joined.iter().map(|(_ent, pos, _vel)| pos.0).collect();
+/// assert_eq!(positions, vec![3]);
+///```
+pub struct Join<Q: JoinQuery> {
+    guards: Q::Guards,
+    sorted: bool, //visit in ascending Entity order; see new_sorted()
+}
+
+///Implemented for tuples of 1 to 6 Fetch types; see Join.
+pub trait JoinQuery {
+    type Guards;
+
+    #[doc(hidden)]
+    fn acquire(world: &World) -> Self::Guards;
+
+    #[doc(hidden)]
+    fn try_acquire(world: &World) -> Option<Self::Guards>;
+
+    #[doc(hidden)]
+    fn count(guards: &Self::Guards) -> usize;
+}
+
+impl<Q: JoinQuery> Join<Q> {
+    ///Blocks until every guard has been acquired.
+    ///## Panics
+    ///Panics if any Component is unregistered, or if the same Component is
+    ///requested more than once where any of those requests is &mut.
+    pub fn new(world: &World) -> Self {
+        Join {
+            guards: Q::acquire(world),
+            sorted: false,
+        }
+    }
+
+    ///Like new(), but iter() and for_each() are guaranteed to visit Entities
+    ///in ascending order, whatever order the Storages hold them in. Costs a
+    ///sort of the driving Storage's Entities per pass. Build one via the
+    ///join_sorted! macro.
+    ///## Panics
+    ///Panics if any Component is unregistered, or if the same Component is
+    ///requested more than once where any of those requests is &mut.
+    pub fn new_sorted(world: &World) -> Self {
+        Join {
+            guards: Q::acquire(world),
+            sorted: true,
+        }
+    }
+
+    ///Non-blocking version of new(). Returns None if any of the Storages is
+    ///currently held in a conflicting way, dropping any guards it had already
+    ///acquired. Build one via the try_join! macro.
+    ///## Panics
+    ///Panics if any Component is unregistered, or if the same Component is
+    ///requested more than once where any of those requests is &mut.
+    pub fn try_new(world: &World) -> Option<Self> {
+        Some(Join {
+            guards: Q::try_acquire(world)?,
+            sorted: false,
+        })
+    }
+
+    ///Number of Entities that have all of the joined Components, e.g. to
+    ///size a buffer before iterating. Scans only the smallest Storage.
+    pub fn count(&self) -> usize {
+        Q::count(&self.guards)
+    }
+}
+
+///The Entities of a Join's driving Storage, either as the HashMap holds them
+///or, for a sorted Join, ascending.
+enum Driver<'a, V> {
+    Unsorted(Keys<'a, Entity, V>),
+    Sorted(vec::IntoIter<Entity>),
+}
+
+impl<'a, V> Driver<'a, V> {
+    fn new(storage: &'a HashMap<Entity, V>, sorted: bool) -> Self {
+        if sorted {
+            let mut entities: Vec<Entity> = storage.keys().copied().collect();
+            entities.sort_unstable();
+            Driver::Sorted(entities.into_iter())
+        } else {
+            Driver::Unsorted(storage.keys())
+        }
+    }
+}
+
+impl<V> Iterator for Driver<'_, V> {
+    type Item = Entity;
+
+    fn next(&mut self) -> Option<Entity> {
+        match self {
+            Driver::Unsorted(keys) => keys.next().copied(),
+            Driver::Sorted(entities) => entities.next(),
+        }
+    }
+}
+
+///Sorts (TypeId, is_write, index) triples into acquisition order.
+///## Panics
+///Panics if the same TypeId appears twice and either appearance is a write,
+///since that thread would then wait on itself forever.
+#[doc(hidden)]
+pub fn acquisition_order<const N: usize>(mut order: [(TypeId, bool, usize); N]) -> [usize; N] {
+    order.sort_unstable_by_key(|&(type_id, _, idx)| (type_id, idx));
+
+    for pair in order.windows(2) {
+        if pair[0].0 == pair[1].0 && (pair[0].1 || pair[1].1) {
+            panic!("attempted to join over the same Storage twice with write access");
+        }
+    }
+
+    order.map(|(_, _, idx)| idx)
+}
+
+macro_rules! impl_join {
+    ($n:literal; $($t:ident $g:ident $idx:tt),+) => {
+        impl<$($t: Fetch),+> JoinQuery for ($($t,)+) {
+            type Guards = ($($t::Guard,)+);
+
+            fn acquire(world: &World) -> Self::Guards {
+                let order = acquisition_order::<$n>([
+                    $((TypeId::of::<$t::Component>(), $t::is_write(), $idx),)+
+                ]);
+
+                $(let mut $g: Option<$t::Guard> = None;)+
+
+                for idx in order {
+                    match idx {
+                        $($idx => $g = Some($t::acquire(world)),)+
+                        _ => unreachable!(),
+                    }
+                }
+
+                ($($g.expect("Join guard not acquired"),)+)
+            }
+
+            fn try_acquire(world: &World) -> Option<Self::Guards> {
+                let order = acquisition_order::<$n>([
+                    $((TypeId::of::<$t::Component>(), $t::is_write(), $idx),)+
+                ]);
+
+                $(let mut $g: Option<$t::Guard> = None;)+
+
+                //Returning early drops whichever guards were already taken.
+                for idx in order {
+                    match idx {
+                        $($idx => $g = Some($t::try_acquire(world)?),)+
+                        _ => unreachable!(),
+                    }
+                }
+
+                Some(($($g.expect("Join guard not acquired"),)+))
+            }
+
+            fn count(guards: &Self::Guards) -> usize {
+                let ($($g,)+) = guards;
+
+                let lens = [$($t::raw($g).len()),+];
+                let smallest = (0..$n).min_by_key(|&idx| lens[idx]).unwrap_or(0);
+
+                let has_all = |e: &&Entity| $($t::raw($g).contains_key(*e))&&+;
+
+                match smallest {
+                    $($idx => $t::raw($g).keys().filter(&has_all).count(),)+
+                    _ => unreachable!(),
+                }
+            }
+        }
+
+        impl<$($t: Fetch),+> Join<($($t,)+)> {
+            ///Visits every Entity that has all of the joined Components.
+            pub fn iter(
+                &self,
+            ) -> impl Iterator<Item = (Entity, $(&$t::Component),+)> + '_ {
+                let driver = impl_join!(@first self.guards; $($t),+);
+                let ($($g,)+) = &self.guards;
+
+                Driver::new(driver, self.sorted).filter_map(move |e| {
+                    Some((e, $($t::raw($g).get(&e)?),+))
+                })
+            }
+
+            ///Calls f for every Entity that has all of the joined Components,
+            ///handing it &mut access to those joined via &mut T.
+            pub fn for_each<Func>(&mut self, mut f: Func)
+            where
+                Func: for<'a> FnMut(Entity, $($t::Item<'a>),+),
+            {
+                //Only Entities with every Component are fetched, since fetching
+                //via &mut T counts as a change for iter_changed_since().
+                let driver: Vec<Entity> = {
+                    let ($($g,)+) = &self.guards;
+                    Driver::new(impl_join!(@first self.guards; $($t),+), self.sorted)
+                        .filter(|e| $($t::raw($g).contains_key(e))&&+)
+                        .collect()
+                };
+
+                let ($($g,)+) = &mut self.guards;
+
+                for e in driver {
+                    if let ($(Some($g),)+) = ($($t::fetch($g, &e),)+) {
+                        f(e, $($g),+);
+                    }
+                }
+            }
+        }
+    };
+    (@first $guards:expr; $t:ident $(, $rest:ident)*) => {
+        $t::raw(&$guards.0)
+    };
+}
+
+impl_join!(1; A g0 0);
+impl_join!(2; A g0 0, B g1 1);
+impl_join!(3; A g0 0, B g1 1, C g2 2);
+impl_join!(4; A g0 0, B g1 1, C g2 2, D g3 3);
+impl_join!(5; A g0 0, B g1 1, C g2 2, D g3 3, E g4 4);
+impl_join!(6; A g0 0, B g1 1, C g2 2, D g3 3, E g4 4, F g5 5);
+
+#[cfg(feature = "rayon")]
+impl<A: Component, B: Component> Join<(&mut A, &mut B)> {
+    ///Like for_each(), but as a rayon ParallelIterator, so the work done per
+    ///Entity is spread over rayon's thread pool.
+    ///
+    ///The returned iterator borrows the write guards held by this Join, so
+    ///both Storages stay locked until the Join itself is dropped, not merely
+    ///until the iterator is. That is why this is a method on a Join rather
+    ///than a free `par_join_mut::<A, B>(&World)`: such a function would have
+    ///to return its guards along with references into them, which Rust can't
+    ///express, and handing out the references without the guards would let
+    ///other threads write to the Storages meanwhile.
+    ///
+    ///The join itself is NOT parallel. Before this returns, the calling
+    ///thread walks both Storages and collects every (Entity, &mut A, &mut B)
+    ///into a Vec, costing time and memory linear in the sizes of the
+    ///Storages; rayon then splits only that Vec. So this pays off when the
+    ///work done per Entity is heavy enough to outweigh that serial pass.
+    ///
+    ///# Example
+    ///```
+    /// use ecs_it::*;
+    /// use rayon::prelude::*;
+    ///
+    /// struct Pos(i32);
+    /// struct Vel(i32);
+    /// impl Component for Pos {}
+    /// impl Component for Vel {}
+    ///
+    /// let world = world::World::new();
+    /// world.register_component::<Pos>();
+    /// world.register_component::<Vel>();
+    ///
+    /// for i in 0..100 {
+    ///     let ent = world.create_entity();
+    ///     world.add_component(ent, Pos(0));
+    ///     world.add_component(ent, Vel(i));
+    /// }
+    ///
+    /// let mut joined = join!(world, &mut Pos, &mut Vel);
+    /// joined.par_iter_mut().for_each(|(_ent, pos, vel)| {
+    ///     pos.0 += vel.0;
+    ///     vel.0 = 0;
+    /// });
+    ///```
+    pub fn par_iter_mut(
+        &mut self,
+    ) -> impl rayon::iter::ParallelIterator<Item = (Entity, &mut A, &mut B)> + '_ {
+        use rayon::iter::IntoParallelIterator;
+        use std::collections::HashSet;
+
+        let (guard_a, guard_b) = &mut self.guards;
+
+        let in_a: HashSet<Entity> = guard_a.raw().keys().copied().collect();
+        let mut in_both: HashMap<Entity, &mut B> = guard_b
+            .iter_entities_mut_where(move |e| in_a.contains(e))
+            .collect();
+
+        let in_b: HashSet<Entity> = in_both.keys().copied().collect();
+        let joined: Vec<(Entity, &mut A, &mut B)> = guard_a
+            .iter_entities_mut_where(move |e| in_b.contains(e))
+            .filter_map(|(e, a)| Some((e, a, in_both.remove(&e)?)))
+            .collect();
+
+        joined.into_par_iter()
+    }
+}
+
+///Joins over several Storages; see Join.
+///
+///`join!(world, &A, &mut B, ...)` expands to `Join::<(&A, &mut B, ...)>::new(&world)`.
+#[macro_export]
+macro_rules! join {
+    ($world:expr, $($t:ty),+ $(,)?) => {
+        $crate::join::Join::<($($t,)+)>::new(&$world)
+    };
+}
+
+///Like join!, but iter() and for_each() visit Entities in ascending order, so
+///every run visits them in the same order; see Join::new_sorted().
+///
+///`join_sorted!(world, &A, &B, ...)` expands to
+///`Join::<(&A, &B, ...)>::new_sorted(&world)`.
+#[macro_export]
+macro_rules! join_sorted {
+    ($world:expr, $($t:ty),+ $(,)?) => {
+        $crate::join::Join::<($($t,)+)>::new_sorted(&$world)
+    };
+}
+
+///Like join!, but never blocks: evaluates to None if any of the Storages is
+///currently held in a conflicting way; see Join::try_new().
+///
+///`try_join!(world, &A, &B, ...)` expands to `Join::<(&A, &B, ...)>::try_new(&world)`.
+#[macro_export]
+macro_rules! try_join {
+    ($world:expr, $($t:ty),+ $(,)?) => {
+        $crate::join::Join::<($($t,)+)>::try_new(&$world)
+    };
+}
@@ -5,7 +5,7 @@
 //!
 //! ECS - Entity-Component-System Architecture
 //!
-//! Entity - A usize which represents an in-diegesis 'thing' in the game.
+//! Entity - A generational index which represents an in-diegesis 'thing' in the game.
 //!
 //! Component - A struct associated with a specific Entity.
 //!
@@ -27,7 +27,8 @@
 //! and only if the intersection of sets being accessed at any given moment between two or more
 //! threads is the null set.
 //!
-//! There is no built-in System API. Implementing Systems is left up to the user of this crate.
+//! Systems can be anything you like, but the optional scheduler::Scheduler will run those which
+//! declare the Storages they read and write, in parallel wherever those sets don't conflict.
 //!
 //! Usage of this crate boils down to calling ecs_it::World::new(...), registering all components,
 //! then requesting access to storages which results in being handed a StorageGuard struct. The
@@ -76,6 +77,11 @@
 //! // You may remove Components from an Entity via the following:
 //! let the_removed_component: Option<ExampleComponent> = world.rm_component::<ExampleComponent>(&entity);
 //!
+//! // When an Entity dies, remove it. Its Components stay in their Storages
+//! // until maintain_ecs() clears them, typically at the end of a game tick:
+//! world.rm_entity(entity);
+//! world.maintain_ecs();
+//!
 //!```
 //!
 //! # How to query the ECS for existing Storages/Components:
@@ -146,12 +152,41 @@
 
 //use std::any::Any;
 
+//Lets the code generated by #[derive(Component)] refer to ::ecs_it from
+//inside this crate too.
+extern crate self as ecs_it;
+
+mod bundle;
 mod entity;
+mod error;
+mod event;
+mod hook;
+pub mod frame_access;
+pub mod join;
+pub mod local_world;
+pub mod query;
+mod resource;
+pub mod scheduler;
+#[cfg(feature = "serde")]
+mod snapshot;
 mod storage;
 pub mod world;
 
-pub type Entity = usize;
+pub use bundle::Bundle;
+pub use ecs_it_derive::Component;
+pub use error::ECSError;
+pub use resource::{ResourceReadGuard, ResourceWriteGuard};
+#[cfg(feature = "serde")]
+pub use snapshot::{SerializedWorld, WorldDelta};
+pub use storage::{AccessPriority, ComponentMask, StorageToken};
+#[cfg(feature = "metrics")]
+pub use storage::LockStats;
+#[cfg(feature = "starvation-watchdog")]
+pub use storage::set_starvation_watchdog;
 
+pub use entity::{Entity, RecyclePolicy};
+
+///Implement via `#[derive(Component)]`, or by hand with `impl Component for T {}`.
 pub trait Component: 'static + Sized + Send + Sync {}
 
 #[cfg(test)]
@@ -160,39 +195,56 @@ mod tests {
     //Must run 'cargo test -- --nocapture' to allow printing of time elapsed
 
     use super::world::World;
-    use super::Component;
+    use super::{Component, Entity};
     use std::time::Instant;
 
+    #[derive(Default)]
     struct TestComponent {
         _val: usize,
     }
     impl Component for TestComponent {}
-    impl Default for TestComponent {
-        fn default() -> Self {
-            TestComponent { _val: 0 }
+
+    ///Spins until cond holds; for tests that must know another thread is
+    ///queued on a Storage before going on.
+    fn wait_until(cond: impl Fn() -> bool) {
+        while !cond() {
+            std::thread::yield_now();
         }
     }
 
     #[test]
     fn entity_tests() {
         let w = World::new();
-        let entity0: usize = w.create_entity();
-        let entity1: usize = w.create_entity();
-        let entity2: usize = w.create_entity();
+        let entity0: Entity = w.create_entity();
+        let entity1: Entity = w.create_entity();
+        let entity2: Entity = w.create_entity();
 
-        assert_eq!(entity0, 0);
-        assert_eq!(entity1, 1);
-        assert_eq!(entity2, 2);
+        assert_eq!(entity0.index(), 0);
+        assert_eq!(entity1.index(), 1);
+        assert_eq!(entity2.index(), 2);
 
         for (i, ent) in w.entity_iter().enumerate() {
             println!("i: {}, ent: {}", i, ent);
         }
+
+        //Dead IDs are recycled before any new ID is minted, and recycling
+        //must not leave a hole in the range of fresh IDs.
+        w.rm_entity(entity1);
+        w.maintain_ecs();
+        let recycled = w.create_entity();
+        assert_eq!(recycled.index(), entity1.index());
+        assert_eq!(w.create_entity().index(), 3);
+
+        //The stale handle must not alias the recycled Entity.
+        assert_ne!(recycled, entity1);
+        assert!(w.is_alive(recycled));
+        assert!(!w.is_alive(entity1));
     }
 
     #[test]
     fn add_component() {
         let w = World::new();
-        let entity0: usize;
+        let entity0: Entity;
         let mut now = Instant::now();
         {
             w.register_component::<TestComponent>();
@@ -206,4 +258,2334 @@ mod tests {
         w.add_component(entity0, TestComponent { _val: 42 });
         println!("Time to add component(): {}", now.elapsed().as_nanos());
     }
+
+    #[test]
+    fn frame_access() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let entity0 = w.create_entity();
+        w.add_component(entity0, TestComponent { _val: 1 });
+
+        {
+            let mut frame = w.frame_access().write::<TestComponent>();
+            for _ in 0..10 {
+                frame.get_mut::<TestComponent>(&entity0).unwrap()._val += 1;
+            }
+            assert_eq!(frame.get::<TestComponent>(&entity0).unwrap()._val, 11);
+        }
+
+        //Frame has ended, so the storage must be accessible again.
+        let guard = w.req_read_guard::<TestComponent>();
+        assert_eq!(guard.get(&entity0).unwrap()._val, 11);
+    }
+
+    #[test]
+    fn frame_access_change_ticks() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let last_run = w.advance_tick();
+        {
+            let mut frame = w.frame_access().write::<TestComponent>();
+            frame.get_mut::<TestComponent>(&ents[1]).unwrap()._val = 5;
+        }
+
+        let guard = w.req_read_guard::<TestComponent>();
+        let changed: Vec<Entity> = guard.iter_changed_since(last_run).map(|(e, _)| e).collect();
+        assert_eq!(changed, vec![ents[1]]);
+    }
+
+    #[test]
+    fn linked_components() {
+        #[derive(Default)]
+        struct LinkedComponent {
+            _val: usize,
+        }
+        impl Component for LinkedComponent {}
+
+        let w = World::new();
+        w.register_linked_components::<(TestComponent, LinkedComponent)>();
+
+        //Spawning with the whole tuple fills every linked Storage together.
+        let entity0 = w.spawn((TestComponent::default(), LinkedComponent::default()));
+        assert!(w.has_component::<TestComponent>(&entity0));
+        assert!(w.has_component::<LinkedComponent>(&entity0));
+        assert_eq!(w.component_count::<TestComponent>(), 1);
+        assert_eq!(w.component_count::<LinkedComponent>(), 1);
+
+        w.rm_entity(entity0);
+        w.maintain_ecs();
+
+        assert!(w.req_read_guard::<TestComponent>().get(&entity0).is_none());
+        assert!(w.req_read_guard::<LinkedComponent>().get(&entity0).is_none());
+    }
+
+    #[test]
+    fn linked_components_register_all_or_none() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        struct Registered;
+        struct Fresh;
+        impl Component for Registered {}
+        impl Component for Fresh {}
+
+        let w = World::new();
+        w.register_component::<Registered>();
+
+        let linked = catch_unwind(AssertUnwindSafe(|| {
+            w.register_linked_components::<(Fresh, Registered)>();
+        }));
+        assert!(linked.is_err());
+        assert!(!w.is_registered::<Fresh>());
+
+        let repeated = catch_unwind(AssertUnwindSafe(|| {
+            w.register_linked_components::<(Fresh, Fresh)>();
+        }));
+        assert!(repeated.is_err());
+        assert!(!w.is_registered::<Fresh>());
+
+        //Neither failure poisoned anything, so registration still works.
+        w.register_linked_components::<(Fresh, TestComponent)>();
+        assert_eq!(w.registered_component_types(), 3);
+    }
+
+    #[test]
+    fn guard_get() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let present = w.create_entity();
+        let absent = w.create_entity();
+        let never_created = Entity::new(999, 0);
+        w.add_component(present, TestComponent { _val: 3 });
+
+        {
+            let guard = w.req_read_guard::<TestComponent>();
+            assert_eq!(guard.get(&present).map(|c| c._val), Some(3));
+            assert!(guard.get(&absent).is_none());
+            assert!(guard.get(&never_created).is_none());
+        }
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        guard.get_mut(&present).unwrap()._val = 4;
+        assert_eq!(guard.get_mut(&present).map(|c| c._val), Some(4));
+        assert!(guard.get_mut(&absent).is_none());
+        assert!(guard.get_mut(&never_created).is_none());
+    }
+
+    #[test]
+    fn has_component() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let entity0 = w.create_entity();
+        let entity1 = w.create_entity();
+        w.add_component(entity0, TestComponent::default());
+
+        assert!(w.has_component::<TestComponent>(&entity0));
+        assert!(!w.has_component::<TestComponent>(&entity1));
+        assert!(w.req_read_guard::<TestComponent>().contains(&entity0));
+    }
+
+    #[test]
+    fn guard_len() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let mut with = Vec::new();
+        for i in 0..4 {
+            let ent = w.create_entity();
+            if i % 2 == 0 {
+                w.add_component(ent, TestComponent::default());
+                with.push(ent);
+            }
+        }
+
+        {
+            let guard = w.req_read_guard::<TestComponent>();
+            assert_eq!(guard.len(), 2);
+            assert!(!guard.is_empty());
+        }
+
+        //Creating Entities without the Component doesn't change len().
+        w.create_entities(10);
+        assert_eq!(w.req_read_guard::<TestComponent>().len(), 2);
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        guard.remove(&with[0]);
+        assert_eq!(guard.len(), 1);
+        assert!(!guard.is_empty());
+
+        guard.remove(&with[1]);
+        assert_eq!(guard.len(), 0);
+        assert!(guard.is_empty());
+    }
+
+    #[test]
+    fn try_req_guards() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        {
+            let _reader = w.req_read_guard::<TestComponent>();
+            assert!(w.try_req_read_guard::<TestComponent>().is_some());
+            assert!(w.try_req_write_guard::<TestComponent>().is_none());
+        }
+
+        {
+            let _writer = w.req_write_guard::<TestComponent>();
+            assert!(w.try_req_read_guard::<TestComponent>().is_none());
+            assert!(w.try_req_write_guard::<TestComponent>().is_none());
+        }
+
+        //A failed attempt must not leave the storage locked.
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    fn req_guard_timeout() {
+        use std::time::Duration;
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let timeout = Duration::from_millis(10);
+
+        {
+            let _reader = w.req_read_guard::<TestComponent>();
+            assert!(w.req_write_guard_timeout::<TestComponent>(timeout).is_none());
+            assert!(w.req_read_guard_timeout::<TestComponent>(timeout).is_some());
+        }
+
+        {
+            let _writer = w.req_write_guard::<TestComponent>();
+            assert!(w.req_read_guard_timeout::<TestComponent>(timeout).is_none());
+        }
+
+        //Timed-out writers must not linger in the accessor state.
+        assert!(w.try_req_read_guard::<TestComponent>().is_some());
+        assert!(w.req_write_guard_timeout::<TestComponent>(timeout).is_some());
+    }
+
+    #[test]
+    fn reader_priority() {
+        use super::AccessPriority;
+        use std::{
+            sync::{Arc, Mutex},
+            thread,
+        };
+
+        let w = Arc::new(World::new());
+        w.register_component_with_priority::<TestComponent>(AccessPriority::Readers);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let writer_guard = w.req_write_guard::<TestComponent>();
+
+        //Queue a writer and a reader behind the held write guard.
+        let (writer_w, writer_order) = (Arc::clone(&w), Arc::clone(&order));
+        let writer = thread::spawn(move || {
+            let _guard = writer_w.req_write_guard::<TestComponent>();
+            writer_order.lock().unwrap().push("write");
+        });
+        let (reader_w, reader_order) = (Arc::clone(&w), Arc::clone(&order));
+        let reader = thread::spawn(move || {
+            let _guard = reader_w.req_read_guard::<TestComponent>();
+            reader_order.lock().unwrap().push("read");
+        });
+        wait_until(|| w.waiting::<TestComponent>() == (1, 1, false));
+
+        drop(writer_guard);
+        reader.join().unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(*order.lock().unwrap(), vec!["read", "write"]);
+    }
+
+    #[test]
+    fn upgradable_guard() {
+        use std::{sync::Arc, thread};
+
+        let w = Arc::new(World::new());
+        w.register_component::<TestComponent>();
+        let entity0 = w.create_entity();
+
+        let upgradable = w.req_upgradable_guard::<TestComponent>();
+        let reader = w.req_read_guard::<TestComponent>();
+        assert!(w.try_req_write_guard::<TestComponent>().is_none());
+        assert!(!upgradable.contains(&entity0));
+
+        //A second upgradable guard must wait for the first.
+        let other_w = Arc::clone(&w);
+        let other = thread::spawn(move || {
+            let other_upgradable = other_w.req_upgradable_guard::<TestComponent>();
+            assert!(other_upgradable.contains(&entity0));
+        });
+
+        //upgrade() must wait for the plain reader to drop.
+        let reader_w = Arc::clone(&w);
+        let reader_thread = thread::spawn(move || {
+            wait_until(|| reader_w.waiting::<TestComponent>().2);
+            drop(reader);
+        });
+
+        let mut writer = upgradable.upgrade();
+        assert!(w.try_req_read_guard::<TestComponent>().is_none());
+        writer.insert(entity0, TestComponent::default());
+        drop(writer);
+
+        reader_thread.join().unwrap();
+        other.join().unwrap();
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    fn derive_component() {
+        #[derive(Component)]
+        struct Derived {
+            _val: usize,
+        }
+
+        #[derive(Component)]
+        struct DerivedGeneric<T> {
+            _val: T,
+        }
+
+        let w = World::new();
+        w.register_component::<Derived>();
+        w.register_component::<DerivedGeneric<u8>>();
+        let entity0 = w.create_entity();
+        w.add_component(entity0, Derived { _val: 1 });
+        w.add_component(entity0, DerivedGeneric { _val: 1u8 });
+    }
+
+    #[test]
+    fn join() {
+        #[derive(Component)]
+        struct Other(usize);
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+
+        let mut both = Vec::new();
+        for i in 0..6 {
+            let ent = w.create_entity();
+            w.add_component(ent, TestComponent { _val: i });
+            if i % 2 == 0 {
+                w.add_component(ent, Other(i));
+                both.push(ent);
+            }
+        }
+
+        crate::join!(w, &mut TestComponent, &Other).for_each(|_, test, other| {
+            test._val += other.0;
+        });
+
+        //Order of types in the join doesn't affect which entities are visited.
+        let joined = crate::join!(w, &Other, &TestComponent);
+        let mut visited: Vec<Entity> = joined
+            .iter()
+            .map(|(ent, other, test)| {
+                assert_eq!(test._val, other.0 * 2);
+                ent
+            })
+            .collect();
+        visited.sort();
+        assert_eq!(visited, both);
+    }
+
+    #[test]
+    #[should_panic(expected = "same Storage twice")]
+    fn join_same_storage_mutably() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let _ = crate::join!(w, &mut TestComponent, &TestComponent);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_load() {
+        #[derive(Component, serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Saved(String);
+
+        let w = World::new();
+        w.register_serializable_component::<Saved>("saved");
+        w.register_component::<TestComponent>();
+
+        let ents: Vec<Entity> = (0..4).map(|_| w.create_entity()).collect();
+        w.add_component(ents[0], Saved("zero".to_string()));
+        w.add_component(ents[2], Saved("two".to_string()));
+        w.rm_entity(ents[1]);
+        w.maintain_ecs();
+        w.rm_entity(ents[3]);
+
+        let snapshot = w.save().unwrap();
+        let json = serde_json::to_string(&snapshot).unwrap();
+
+        //Mutate the World so that loading has something to undo.
+        w.add_component(ents[0], TestComponent::default());
+        w.rm_component::<Saved>(&ents[2]);
+        let extra = w.create_entity();
+
+        w.load(&serde_json::from_str(&json).unwrap()).unwrap();
+
+        {
+            let saved = w.req_read_guard::<Saved>();
+            assert_eq!(saved.get(&ents[0]), Some(&Saved("zero".to_string())));
+            assert_eq!(saved.get(&ents[2]), Some(&Saved("two".to_string())));
+            assert_eq!(saved.len(), 2);
+            assert!(w.req_read_guard::<TestComponent>().is_empty());
+        }
+        assert!(w.is_alive(ents[0]) && w.is_alive(ents[2]));
+        assert!(!w.is_alive(ents[1]) && !w.is_alive(ents[3]) && !w.is_alive(extra));
+
+        //The free-list survives too: the next Entity reuses index 1.
+        assert_eq!(w.create_entity().index(), ents[1].index());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_is_atomic() {
+        #[derive(Component, serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Name(String);
+        #[derive(Component, serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Hp(u32);
+
+        let w = World::new();
+        w.register_serializable_component::<Name>("name");
+        w.register_serializable_component::<Hp>("hp");
+        let ent = w.create_entity();
+        w.add_component(ent, Name("kept".to_string()));
+        w.add_component(ent, Hp(3));
+
+        //"name" sorts and loads before "hp", whose value no longer fits.
+        let mut json = serde_json::to_value(w.save().unwrap()).unwrap();
+        json["components"]["hp"][0][1] = serde_json::json!("not a number");
+        w.create_entity();
+        w.rm_component::<Name>(&ent);
+
+        let bad = serde_json::from_value(json).unwrap();
+        assert!(matches!(w.load(&bad), Err(crate::ECSError::Deserialize { .. })));
+
+        assert_eq!(w.entity_count(), 2);
+        assert!(w.req_read_guard::<Name>().is_empty());
+        assert_eq!(w.req_read_guard::<Hp>().get(&ent), Some(&Hp(3)));
+    }
+
+    #[test]
+    fn iter_entities() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..4).map(|_| w.create_entity()).collect();
+        w.add_component(ents[1], TestComponent { _val: 1 });
+        w.add_component(ents[3], TestComponent { _val: 3 });
+
+        for (ent, comp) in w.req_write_guard::<TestComponent>().iter_entities_mut() {
+            comp._val += ent.index() as usize;
+        }
+
+        let guard = w.req_read_guard::<TestComponent>();
+        let mut pairs: Vec<(Entity, usize)> =
+            guard.iter_entities().map(|(e, c)| (e, c._val)).collect();
+        pairs.sort();
+        assert_eq!(pairs, vec![(ents[1], 2), (ents[3], 6)]);
+    }
+
+    #[test]
+    fn clear_storage() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..8).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let capacity = w.req_read_guard::<TestComponent>().capacity();
+        w.clear_storage::<TestComponent>();
+
+        {
+            let guard = w.req_read_guard::<TestComponent>();
+            assert!(guard.is_empty());
+            assert_eq!(guard.capacity(), capacity);
+        }
+
+        assert!(w.is_alive(ents[7]));
+        w.add_component(ents[7], TestComponent::default());
+        assert!(w.has_component::<TestComponent>(&ents[7]));
+    }
+
+    #[test]
+    fn drain() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..4).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        let mut drained: Vec<Entity> = guard.drain().map(|(e, _)| e).collect();
+        drained.sort();
+        assert_eq!(drained, ents);
+        assert!(guard.is_empty());
+
+        //Partially consuming the drain must still leave an empty, usable Storage.
+        for ent in ents.iter() {
+            guard.insert(*ent, TestComponent::default());
+        }
+        assert_eq!(guard.drain().take(1).count(), 1);
+        assert!(guard.is_empty());
+        assert!(guard.insert(ents[0], TestComponent::default()).is_none());
+    }
+
+    #[test]
+    fn retain() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..6).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let mut visited = Vec::new();
+        w.req_write_guard::<TestComponent>().retain(|ent, _| {
+            visited.push(ent);
+            ent.index() % 2 == 1
+        });
+        assert_eq!(visited, ents);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        for ent in ents.iter() {
+            assert_eq!(guard.contains(ent), ent.index() % 2 == 1);
+        }
+    }
+
+    #[test]
+    fn swap() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        w.add_component(ents[0], TestComponent { _val: 0 });
+        w.add_component(ents[1], TestComponent { _val: 1 });
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        guard.swap(ents[0], ents[1]);
+        assert_eq!(guard.get_mut(&ents[0]).unwrap()._val, 1);
+        assert_eq!(guard.get_mut(&ents[1]).unwrap()._val, 0);
+
+        //Swapping with an Entity that has no Component moves it over.
+        guard.swap(ents[1], ents[2]);
+        assert!(guard.get_mut(&ents[1]).is_none());
+        assert_eq!(guard.get_mut(&ents[2]).unwrap()._val, 0);
+    }
+
+    #[test]
+    fn get_many_mut() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        w.add_component(ents[0], TestComponent { _val: 0 });
+        w.add_component(ents[1], TestComponent { _val: 1 });
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        {
+            let [a, b] = guard.get_many_mut([ents[0], ents[1]]).unwrap();
+            std::mem::swap(&mut a._val, &mut b._val);
+        }
+        assert_eq!(guard.get_mut(&ents[0]).unwrap()._val, 1);
+
+        assert!(guard.get_many_mut([ents[0], ents[0]]).is_none());
+        assert!(guard.get_many_mut([ents[0], ents[2]]).is_none());
+    }
+
+    #[test]
+    fn change_detection() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let last_run = w.advance_tick();
+        assert_eq!(w.current_tick(), 1);
+        assert_eq!(
+            w.req_read_guard::<TestComponent>()
+                .iter_changed_since(last_run)
+                .count(),
+            0
+        );
+
+        w.req_write_guard::<TestComponent>().get_mut(&ents[1]).unwrap()._val = 5;
+        w.rm_component::<TestComponent>(&ents[2]);
+
+        //An Entry left vacant is no change.
+        let _ = w.req_write_guard::<TestComponent>().entry(ents[2]);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        let changed: Vec<Entity> = guard.iter_changed_since(last_run).map(|(e, _)| e).collect();
+        assert_eq!(changed, vec![ents[1]]);
+        assert_eq!(guard.iter_changed_since(0).count(), 2);
+        assert_eq!(guard.changed_rows(), 2);
+    }
+
+    #[test]
+    fn join_change_detection() {
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+        w.add_component(ents[0], Other);
+
+        //TestComponent drives, so ents[1] and ents[2] are visited but lack Other.
+        let last_run = w.advance_tick();
+        crate::join!(w, &mut TestComponent, &Other).for_each(|_, test, _| test._val += 1);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        let changed: Vec<Entity> = guard.iter_changed_since(last_run).map(|(e, _)| e).collect();
+        assert_eq!(changed, vec![ents[0]]);
+    }
+
+    #[test]
+    fn resources() {
+        struct GameClock(u64);
+
+        let w = World::new();
+        assert!(w.insert_resource(GameClock(0)).is_none());
+
+        w.get_resource_mut::<GameClock>().0 += 1;
+        assert_eq!(w.get_resource::<GameClock>().0, 1);
+
+        let old = w.insert_resource(GameClock(10)).unwrap();
+        assert_eq!(old.0, 1);
+        assert_eq!(w.get_resource::<GameClock>().0, 10);
+    }
+
+    #[test]
+    fn events() {
+        struct Damage(u32);
+
+        let w = World::new();
+        assert!(w.drain_events::<Damage>().is_empty());
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..3 {
+                    w.send_event(Damage(i));
+                }
+            })
+            .join()
+            .unwrap();
+
+            s.spawn(|| {
+                let drained: Vec<u32> = w.drain_events::<Damage>().iter().map(|d| d.0).collect();
+                assert_eq!(drained, vec![0, 1, 2]);
+            });
+        });
+
+        assert!(w.drain_events::<Damage>().is_empty());
+    }
+
+    #[test]
+    fn scheduler() {
+        use super::scheduler::Scheduler;
+        use std::{
+            any::TypeId,
+            sync::{Arc, Barrier},
+        };
+
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+
+        //Both systems only read, so they must run concurrently or the
+        //barrier would never release either of them.
+        let barrier = Arc::new(Barrier::new(2));
+        let mut scheduler = Scheduler::new();
+        for _ in 0..2 {
+            let barrier = Arc::clone(&barrier);
+            scheduler.add_system(
+                move |w: &World| {
+                    let _guard = w.req_read_guard::<TestComponent>();
+                    barrier.wait();
+                },
+                &[TypeId::of::<TestComponent>()],
+                &[],
+            );
+        }
+
+        //These two conflict, so must run in the order added.
+        let tc = TypeId::of::<TestComponent>();
+        scheduler.add_system(
+            move |w: &World| w.req_write_guard::<TestComponent>().get_mut(&ent).unwrap()._val = 1,
+            &[],
+            &[tc],
+        );
+        scheduler.add_system(
+            move |w: &World| w.req_write_guard::<TestComponent>().get_mut(&ent).unwrap()._val *= 5,
+            &[],
+            &[tc, TypeId::of::<Other>()],
+        );
+
+        scheduler.build().unwrap();
+        scheduler.run_parallel(&w).unwrap();
+        assert_eq!(w.req_read_guard::<TestComponent>().get(&ent).unwrap()._val, 5);
+    }
+
+    #[test]
+    fn scheduler_ordering() {
+        use super::scheduler::{Scheduler, SchedulerError, SystemLabel};
+        use std::sync::{Arc, Mutex};
+
+        let w = World::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let logger = |name: &'static str| {
+            let log = Arc::clone(&log);
+            move |_: &World| log.lock().unwrap().push(name)
+        };
+
+        //Added in reverse, and touching no Storages, so only the labels order them.
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system_after(None, logger("collision"), &[], &[], &[SystemLabel("movement")]);
+        scheduler.add_system_after(
+            Some(SystemLabel("movement")),
+            logger("movement"),
+            &[],
+            &[],
+            &[SystemLabel("input")],
+        );
+        scheduler.add_labeled_system(SystemLabel("input"), logger("input"), &[], &[]);
+
+        scheduler.build().unwrap();
+        scheduler.run_parallel(&w).unwrap();
+        assert_eq!(*log.lock().unwrap(), vec!["input", "movement", "collision"]);
+
+        let mut cyclic = Scheduler::new();
+        cyclic.add_system_after(Some(SystemLabel("a")), |_: &World| {}, &[], &[], &[SystemLabel("b")]);
+        cyclic.add_system_after(Some(SystemLabel("b")), |_: &World| {}, &[], &[], &[SystemLabel("a")]);
+        assert_eq!(
+            cyclic.build(),
+            Err(SchedulerError::Cycle(vec![SystemLabel("a"), SystemLabel("b")]))
+        );
+
+        //Systems blocked by a cycle, but not on it, aren't reported as in it.
+        let mut downstream = Scheduler::new();
+        for (label, after) in [("c", "a"), ("a", "b"), ("b", "a"), ("d", "c")] {
+            let (label, after) = (SystemLabel(label), SystemLabel(after));
+            downstream.add_system_after(Some(label), |_: &World| {}, &[], &[], &[after]);
+        }
+        assert_eq!(
+            downstream.build(),
+            Err(SchedulerError::Cycle(vec![SystemLabel("a"), SystemLabel("b")]))
+        );
+
+        let mut unknown = Scheduler::new();
+        unknown.add_system_after(None, |_: &World| {}, &[], &[], &[SystemLabel("nope")]);
+        assert_eq!(unknown.build(), Err(SchedulerError::UnknownLabel(SystemLabel("nope"))));
+    }
+
+    #[test]
+    fn try_register_component() {
+        let w = World::new();
+        assert!(w.try_register_component::<TestComponent>().is_ok());
+
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent { _val: 3 });
+
+        assert!(w.try_register_component::<TestComponent>().is_err());
+        assert_eq!(w.req_read_guard::<TestComponent>().get(&ent).unwrap()._val, 3);
+    }
+
+    #[test]
+    fn create_entities() {
+        let w = World::new();
+        let first = w.create_entities(3);
+        w.rm_entity(first[1]);
+        w.maintain_ecs();
+
+        let batch = w.create_entities(3);
+        assert_eq!(batch[0].index(), first[1].index());
+        assert_eq!(batch[0].generation(), first[1].generation() + 1);
+        assert_eq!(batch[1].index(), 3);
+        assert_eq!(batch[2].index(), 4);
+        assert_eq!(w.entity_iter().count(), 5);
+        assert!(w.entity_iter().is_sorted());
+    }
+
+    #[test]
+    fn add_components() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents = w.create_entities(4);
+
+        w.add_components(ents.iter().map(|e| (*e, TestComponent { _val: 1 })));
+        w.req_write_guard::<TestComponent>()
+            .insert_many([(ents[0], TestComponent { _val: 7 })]);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        assert_eq!(guard.len(), 4);
+        assert_eq!(guard.get(&ents[0]).unwrap()._val, 7);
+        assert_eq!(guard.get(&ents[3]).unwrap()._val, 1);
+    }
+
+    #[test]
+    fn register_after_entities() {
+        //Storages don't track the Entity count, so one registered after
+        //Entities exist must still accept Components for all of them.
+        let w = World::new();
+        let ents = w.create_entities(5);
+        w.register_component::<TestComponent>();
+
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+
+        let guard = w.req_read_guard::<TestComponent>();
+        assert_eq!(guard.len(), 5);
+        assert!(ents.iter().all(|ent| guard.contains(ent)));
+    }
+
+    #[test]
+    fn register_component_with_capacity() {
+        let w = World::new();
+        w.register_component_with_capacity::<TestComponent>(5000);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        assert!(guard.is_empty());
+        assert!(guard.capacity() >= 5000);
+    }
+
+    #[test]
+    fn counts() {
+        #[derive(Component)]
+        struct Hp;
+        #[derive(Component)]
+        struct MaxHp;
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_linked_components::<(Hp, MaxHp)>();
+        assert_eq!(w.registered_component_types(), 3);
+
+        let ents = w.create_entities(3);
+        w.add_component(ents[0], TestComponent::default());
+        w.rm_entity(ents[1]);
+
+        assert_eq!(w.entity_count(), 2);
+        assert_eq!(w.component_count::<TestComponent>(), 1);
+    }
+
+    #[test]
+    fn describe_entity() {
+        #[derive(Debug)]
+        struct Name {
+            _name: &'static str,
+        }
+        impl Component for Name {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_debug_component::<Name>();
+
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+        w.add_component(ent, Name { _name: "goblin" });
+
+        assert_eq!(w.describe_entity(ent), vec![String::from("Name { _name: \"goblin\" }")]);
+        assert!(w.describe_entity(w.create_entity()).is_empty());
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ent = w.create_entity();
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        guard.get_or_insert_with(ent, || TestComponent { _val: 1 })._val += 1;
+        guard.get_or_insert_with(ent, || TestComponent { _val: 100 })._val += 1;
+        assert_eq!(guard.raw()[&ent]._val, 3);
+    }
+
+    #[test]
+    fn add_component_lazy() {
+        let w = World::new();
+        let ents = w.create_entities(2);
+
+        assert!(w.add_component_lazy(ents[0], TestComponent::default()).is_none());
+        assert!(w.add_component_lazy(ents[1], TestComponent::default()).is_none());
+        assert_eq!(w.component_count::<TestComponent>(), 2);
+    }
+
+    #[test]
+    fn despawn_now() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents = w.create_entities(2);
+        w.add_components(ents.iter().map(|e| (*e, TestComponent::default())));
+
+        w.despawn_now(ents[0]);
+        assert!(!w.is_alive(ents[0]));
+        assert!(!w.has_component::<TestComponent>(&ents[0]));
+        assert!(w.has_component::<TestComponent>(&ents[1]));
+
+        //Already recycled, without waiting on maintain_ecs().
+        assert_eq!(w.create_entity().index(), ents[0].index());
+    }
+
+    #[test]
+    fn count_with() {
+        #[derive(Component)]
+        struct Tag;
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Tag>();
+        let ents = w.create_entities(5);
+
+        w.add_components(ents.iter().map(|e| (*e, TestComponent::default())));
+        w.add_components([(ents[1], Tag), (ents[3], Tag)]);
+
+        assert_eq!(w.count_with::<(&TestComponent, &Tag)>(), 2);
+        assert_eq!(w.count_with::<(&Tag, &mut TestComponent)>(), 2);
+    }
+
+    #[test]
+    fn shrink_storages() {
+        let w = World::new();
+        w.register_component_with_capacity::<TestComponent>(1000);
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+
+        w.shrink_storages();
+
+        let guard = w.req_read_guard::<TestComponent>();
+        assert!(guard.capacity() < 1000);
+        assert!(guard.contains(&ent));
+    }
+
+    #[test]
+    fn try_add_rm_component() {
+        use super::ECSError;
+
+        let w = World::new();
+        let ent = w.create_entity();
+
+        assert!(w.try_add_component(ent, TestComponent::default()).is_err());
+        assert!(w.try_rm_component::<TestComponent>(&ent).is_err());
+
+        w.register_component::<TestComponent>();
+        assert!(matches!(w.try_add_component(ent, TestComponent::default()), Ok(None)));
+
+        w.rm_entity(ent);
+        assert!(matches!(
+            w.try_add_component(ent, TestComponent::default()),
+            Err(ECSError::NotAlive { entity }) if entity == ent
+        ));
+        assert!(w.try_rm_component::<TestComponent>(&ent).unwrap().is_some());
+    }
+
+    #[test]
+    fn get_many() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents = w.create_entities(3);
+        w.add_component(ents[0], TestComponent { _val: 1 });
+        w.add_component(ents[2], TestComponent { _val: 3 });
+
+        //ents[1] becomes a stale handle; never_minted is out of range.
+        w.rm_entity(ents[1]);
+        w.maintain_ecs();
+        let never_minted = Entity::new(99, 4);
+
+        let query = [ents[2], ents[1], never_minted, ents[0]];
+        let guard = w.req_read_guard::<TestComponent>();
+
+        let vals: Vec<Option<usize>> = guard
+            .get_many(&query)
+            .iter()
+            .map(|comp| comp.map(|c| c._val))
+            .collect();
+        assert_eq!(vals, vec![Some(3), None, None, Some(1)]);
+
+        let present: Vec<Entity> = guard.get_present(&query).map(|(e, _)| e).collect();
+        assert_eq!(present, vec![ents[2], ents[0]]);
+    }
+
+    #[test]
+    fn fifo_writers() {
+        use super::AccessPriority;
+        use std::{
+            sync::{Arc, Mutex},
+            thread,
+            time::Duration,
+        };
+
+        let w = Arc::new(World::new());
+        w.register_component_with_priority::<TestComponent>(AccessPriority::FifoWriters);
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        let blocker = w.req_write_guard::<TestComponent>();
+        let mut writers = Vec::new();
+        for id in 0..4 {
+            let (writer_w, order) = (Arc::clone(&w), Arc::clone(&order));
+            writers.push(thread::spawn(move || {
+                let _guard = writer_w.req_write_guard::<TestComponent>();
+                order.lock().unwrap().push(id);
+            }));
+            //Make sure each writer is queued before the next one asks.
+            wait_until(|| w.waiting::<TestComponent>().1 == id + 1);
+        }
+
+        //A timed-out writer must not hold up the ones queued behind it.
+        assert!(w
+            .req_write_guard_timeout::<TestComponent>(Duration::from_millis(10))
+            .is_none());
+
+        drop(blocker);
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2, 3]);
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn deadlock_detection() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        {
+            let _reader = w.req_read_guard::<TestComponent>();
+            let _other_reader = w.req_read_guard::<TestComponent>();
+            let relock = catch_unwind(AssertUnwindSafe(|| w.req_write_guard::<TestComponent>()));
+            assert!(relock.is_err());
+        }
+
+        //Both released above, so this must not be flagged.
+        drop(w.req_write_guard::<TestComponent>());
+
+        //Readers of different Storages may nest in either order.
+        struct Other;
+        impl Component for Other {}
+        w.register_component::<Other>();
+        let _ = (w.req_read_guard::<TestComponent>().len(), w.req_read_guard::<Other>().len());
+        let _ = (w.req_read_guard::<Other>().len(), w.req_read_guard::<TestComponent>().len());
+    }
+
+    #[test]
+    fn rm_component_by_type() {
+        let w = World::new();
+        w.register_component_named::<TestComponent>("test");
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+
+        assert!(!w.rm_component_by_type("nope", ent));
+        assert!(w.rm_component_by_type("test", ent));
+        assert!(!w.rm_component_by_type("test", ent));
+        assert!(!w.has_component::<TestComponent>(&ent));
+    }
+
+    #[test]
+    fn readers_overflow() {
+        use super::{
+            storage::{Accessor, ReadAccess},
+            AccessPriority,
+        };
+
+        let accessor = Accessor::new(AccessPriority::default(), "readers_overflow");
+        //Stub in all but one of the readers a Storage can count.
+        accessor.mtx.lock().unwrap().readers = u16::MAX - 1;
+
+        assert!(accessor.try_init_read_access());
+        assert!(!accessor.try_init_read_access());
+
+        accessor.drop_read_access(ReadAccess::Counted);
+        assert!(accessor.try_init_read_access());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_iter_mut() {
+        use rayon::iter::ParallelIterator;
+
+        struct Other(usize);
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+
+        let ents = w.create_entities(1000);
+        for (i, ent) in ents.iter().enumerate() {
+            w.add_component(*ent, TestComponent::default());
+            if i % 2 == 0 {
+                w.add_component(*ent, Other(i));
+            }
+        }
+
+        let start_tick = w.advance_tick();
+        crate::join!(w, &mut TestComponent, &mut Other)
+            .par_iter_mut()
+            .for_each(|(_, test, other)| {
+                test._val = other.0;
+                other.0 = 0;
+            });
+
+        let test_guard = w.req_read_guard::<TestComponent>();
+        assert_eq!(test_guard.iter_changed_since(start_tick).count(), 500);
+        for (i, ent) in ents.iter().enumerate() {
+            let expected = if i % 2 == 0 { i } else { 0 };
+            assert_eq!(test_guard.get(ent).unwrap()._val, expected);
+        }
+    }
+
+    #[test]
+    fn move_component() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let [a, b, c] = [w.create_entity(), w.create_entity(), w.create_entity()];
+
+        w.add_component(a, TestComponent { _val: 1 });
+        w.add_component(b, TestComponent { _val: 2 });
+
+        let displaced = w.move_component::<TestComponent>(a, b);
+        assert_eq!(displaced.map(|t| t._val), Some(2));
+        assert!(!w.has_component::<TestComponent>(&a));
+        assert_eq!(w.req_read_guard::<TestComponent>().get(&b).unwrap()._val, 1);
+
+        //Nothing to move: the target keeps its Component.
+        assert!(w.move_component::<TestComponent>(c, b).is_none());
+        assert!(w.has_component::<TestComponent>(&b));
+
+        assert!(w.move_component::<TestComponent>(b, b).is_none());
+        assert!(w.has_component::<TestComponent>(&b));
+    }
+
+    #[test]
+    fn first_and_find() {
+        #[derive(Clone)]
+        struct Camera(u32);
+        impl Component for Camera {}
+
+        let w = World::new();
+        w.register_component::<Camera>();
+        assert!(w.get_single::<Camera>().is_none());
+
+        let ents = w.create_entities(5);
+        for ent in ents.iter().rev() {
+            w.add_component(*ent, Camera(ent.index() * 10));
+        }
+
+        let guard = w.req_read_guard::<Camera>();
+        assert_eq!(guard.first().map(|(e, _)| e), Some(ents[0]));
+        assert_eq!(guard.find(|c| c.0 > 15).map(|(e, c)| (e, c.0)), Some((ents[2], 20)));
+        assert!(guard.find(|c| c.0 > 100).is_none());
+        drop(guard);
+
+        let (ent, camera) = w.get_single::<Camera>().unwrap();
+        assert_eq!((ent, camera.0), (ents[0], 0));
+    }
+
+    #[test]
+    fn get_cloned() {
+        #[derive(Clone)]
+        struct Health(i32);
+        impl Component for Health {}
+
+        let w = World::new();
+        w.register_component::<Health>();
+        let ent = w.create_entity();
+        assert!(w.get_cloned::<Health>(&ent).is_none());
+
+        w.add_component(ent, Health(10));
+        let health = w.get_cloned::<Health>(&ent).unwrap();
+        assert_eq!(health.0, 10);
+
+        //The read guard is already gone, so a writer isn't blocked.
+        assert!(w.try_req_write_guard::<Health>().is_some());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn lock_stats() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        //Uncontended requests never sleep, so they aren't counted.
+        drop(w.req_read_guard::<TestComponent>());
+        drop(w.req_write_guard::<TestComponent>());
+        assert_eq!(w.lock_stats::<TestComponent>(), Default::default());
+
+        let guard = w.req_write_guard::<TestComponent>();
+        std::thread::scope(|s| {
+            let reader = s.spawn(|| drop(w.req_read_guard::<TestComponent>()));
+            wait_until(|| w.waiting::<TestComponent>().0 == 1);
+            drop(guard);
+            reader.join().unwrap();
+        });
+
+        let stats = w.lock_stats::<TestComponent>();
+        assert_eq!((stats.total_read_waits, stats.total_write_waits), (1, 0));
+        assert!(stats.read_wait_time > std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn with_read_and_write() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ent = w.create_entity();
+
+        let old = w.with_write::<TestComponent, _>(|guard| {
+            guard.insert(ent, TestComponent { _val: 7 })
+        });
+        assert!(old.is_none());
+
+        let val = w.with_read::<TestComponent, _>(|guard| guard.get(&ent).map(|t| t._val));
+        assert_eq!(val, Some(7));
+
+        //Both guards are gone by now.
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    fn unregister_component() {
+        use super::ECSError;
+
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        assert!(matches!(
+            w.unregister_component::<TestComponent>(),
+            Err(ECSError::Unregistered { .. })
+        ));
+
+        w.register_component_named::<TestComponent>("test");
+        w.register_component::<Other>();
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+        w.add_component(ent, Other);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        assert!(matches!(
+            w.unregister_component::<TestComponent>(),
+            Err(ECSError::Borrowed { .. })
+        ));
+        drop(guard);
+
+        assert!(w.unregister_component::<TestComponent>().is_ok());
+        assert!(w.try_rm_component::<TestComponent>(&ent).is_err());
+        assert!(!w.rm_component_by_type("test", ent));
+        assert!(w.has_component::<Other>(&ent));
+
+        //Maintenance no longer touches the removed Storage.
+        w.rm_entity(ent);
+        w.maintain_ecs();
+        assert_eq!(w.component_count::<Other>(), 0);
+
+        w.register_component_named::<TestComponent>("test");
+        assert_eq!(w.component_count::<TestComponent>(), 0);
+    }
+
+    #[test]
+    fn reset() {
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+
+        let ents = w.create_entities(10);
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+        w.add_component(ents[3], Other);
+        w.rm_entity(ents[5]);
+
+        w.reset();
+        assert_eq!(w.entity_count(), 0);
+        assert_eq!(w.component_count::<TestComponent>(), 0);
+        assert_eq!(w.component_count::<Other>(), 0);
+
+        //Registrations survive, and indices start over.
+        let ent = w.create_entity();
+        assert_eq!((ent.index(), ent.generation()), (0, 0));
+        w.add_component(ent, Other);
+        assert!(w.has_component::<Other>(&ent));
+        assert!(!w.has_component::<TestComponent>(&ent));
+    }
+
+    #[test]
+    fn reserve_entity() {
+        use std::collections::HashSet;
+
+        const THREADS: usize = 8;
+        const PER_THREAD: usize = 10_000;
+
+        let spawn_all = |w: &World, spawn: fn(&World) -> Entity| -> Vec<Entity> {
+            std::thread::scope(|s| {
+                let handles: Vec<_> = (0..THREADS)
+                    .map(|_| {
+                        s.spawn(move || (0..PER_THREAD).map(|_| spawn(w)).collect::<Vec<_>>())
+                    })
+                    .collect();
+
+                handles.into_iter().flat_map(|h| h.join().unwrap()).collect()
+            })
+        };
+
+        let w = World::new();
+        let reserved = spawn_all(&w, |w| w.reserve_entity().unwrap());
+
+        let unique: HashSet<Entity> = reserved.iter().copied().collect();
+        assert_eq!(unique.len(), THREADS * PER_THREAD);
+        assert_eq!(w.entity_count(), THREADS * PER_THREAD);
+        assert!(w.is_alive(reserved[0]));
+
+        //Both paths draw from the same pool of fresh indices.
+        let created = w.create_entity();
+        let reserved = w.reserve_entity().unwrap();
+        assert_eq!(created.index() as usize, THREADS * PER_THREAD);
+        assert_eq!(reserved.index(), created.index() + 1);
+
+        //Only create_entity() recycles.
+        w.rm_entity(created);
+        w.maintain_ecs();
+        assert_ne!(w.reserve_entity().unwrap().index(), created.index());
+        assert_eq!(w.create_entity().index(), created.index());
+    }
+
+    #[test]
+    fn component_masks() {
+        use super::ComponentMask;
+
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let test_mask = w.component_mask::<TestComponent>();
+        let both = test_mask | w.component_mask::<Other>();
+
+        let [a, b] = [w.create_entity(), w.create_entity()];
+        assert!(w.matches(a, &ComponentMask::empty()));
+        assert!(!w.matches(a, &test_mask));
+
+        w.add_component(a, TestComponent::default());
+        w.add_component(a, Other);
+        w.req_write_guard::<TestComponent>().entry(b).or_default();
+        assert!(w.matches(a, &both));
+        assert!(w.matches(b, &test_mask) && !w.matches(b, &both));
+
+        w.rm_component::<TestComponent>(&b);
+        w.req_write_guard::<TestComponent>().swap(a, b);
+        assert!(w.matches(b, &test_mask));
+        assert!(!w.matches(a, &test_mask) && w.matches(a, &w.component_mask::<Other>()));
+
+        w.rm_entity(a);
+        w.maintain_ecs();
+        assert!(w.entity_mask(a).is_empty());
+
+        w.req_write_guard::<TestComponent>().raw_mut().clear();
+        assert!(!w.matches(b, &test_mask));
+
+        w.add_component(b, Other);
+        w.unregister_component::<Other>().unwrap();
+        assert!(w.entity_mask(b).is_empty());
+    }
+
+    #[test]
+    fn stale_handle_masks() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let mask = w.component_mask::<TestComponent>();
+
+        let stale = w.create_entity();
+        w.rm_entity(stale);
+        w.maintain_ecs();
+        let live = w.create_entity();
+        assert_eq!(live.index(), stale.index());
+
+        w.add_component(live, TestComponent::default());
+        assert!(w.rm_component::<TestComponent>(&stale).is_none());
+        assert!(w.matches(live, &mask));
+        assert!(!w.matches(stale, &mask));
+
+        w.rm_component::<TestComponent>(&live);
+        w.req_write_guard::<TestComponent>().insert(stale, TestComponent::default());
+        assert!(!w.matches(live, &mask));
+    }
+
+    #[test]
+    fn insert_tracked() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ents = w.create_entities(2);
+
+        let mut guard = w.req_write_guard::<TestComponent>();
+        let (old, grew) = guard.insert_tracked(ents[0], TestComponent::default());
+        assert!(old.is_none() && grew);
+
+        //Replacing never needs more room.
+        let (old, grew) = guard.insert_tracked(ents[0], TestComponent::default());
+        assert!(old.is_some() && !grew);
+
+        let spare = guard.capacity() - guard.len();
+        let grew = (0..spare).any(|_| {
+            let ent = w.create_entity();
+            guard.insert_tracked(ent, TestComponent::default()).1
+        });
+        assert!(!grew);
+        assert!(guard.insert_tracked(ents[1], TestComponent::default()).1);
+    }
+
+    #[test]
+    fn for_each() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        for ent in w.create_entities(3) {
+            w.add_component(ent, TestComponent { _val: ent.index() as usize });
+        }
+
+        w.for_each_mut::<TestComponent>(|ent, test| test._val += ent.index() as usize);
+
+        let mut sum = 0;
+        w.for_each::<TestComponent>(|_, test| sum += test._val);
+        assert_eq!(sum, 6);
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        #[derive(Clone)]
+        struct Hp(u32);
+        impl Component for Hp {}
+
+        let w = World::new();
+        w.register_component::<Hp>();
+        let [a, b] = [w.create_entity(), w.create_entity()];
+        w.add_component(a, Hp(10));
+
+        let checkpoint = w.req_read_guard::<Hp>().snapshot();
+
+        w.add_component(a, Hp(3));
+        w.add_component(b, Hp(7));
+
+        let redo = w.req_write_guard::<Hp>().restore(checkpoint);
+        assert_eq!(w.get_cloned::<Hp>(&a).map(|hp| hp.0), Some(10));
+        assert!(!w.has_component::<Hp>(&b));
+        assert!(!w.matches(b, &w.component_mask::<Hp>()));
+
+        w.req_write_guard::<Hp>().restore(redo);
+        assert_eq!(w.get_cloned::<Hp>(&a).map(|hp| hp.0), Some(3));
+        assert!(w.matches(b, &w.component_mask::<Hp>()));
+    }
+
+    #[test]
+    fn component_types() {
+        use std::any::TypeId;
+
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let [a, b] = [w.create_entity(), w.create_entity()];
+
+        w.add_component(a, TestComponent::default());
+        w.add_component(a, Other);
+        w.add_component(b, Other);
+
+        let mut expected = vec![TypeId::of::<TestComponent>(), TypeId::of::<Other>()];
+        expected.sort_unstable();
+        assert_eq!(w.component_types(a), expected);
+        assert_eq!(w.component_types(b), vec![TypeId::of::<Other>()]);
+        assert!(w.component_types(w.create_entity()).is_empty());
+    }
+
+    #[test]
+    fn uncontended_reads() {
+        const READS: usize = 100_000;
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        //Writers must still exclude readers that took the fast path.
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent { _val: 0 });
+        std::thread::scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|| {
+                    for _ in 0..READS / 10 {
+                        let guard = w.req_read_guard::<TestComponent>();
+                        assert_eq!(guard.get(&ent).unwrap()._val % 2, 0);
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..READS / 100 {
+                    let mut guard = w.req_write_guard::<TestComponent>();
+                    guard.get_mut(&ent).unwrap()._val += 1;
+                    std::thread::yield_now();
+                    guard.get_mut(&ent).unwrap()._val += 1;
+                }
+            });
+        });
+    }
+
+    #[test]
+    fn double_buffered() {
+        struct Transform(i32);
+        impl Component for Transform {}
+
+        let w = World::new();
+        w.register_double_buffered::<Transform>();
+        let [a, b] = [w.create_entity(), w.create_entity()];
+
+        w.add_component(a, Transform(1));
+        w.swap_buffers::<Transform>();
+        assert!(!w.has_component::<Transform>(&a));
+        w.add_component(a, Transform(2));
+        w.add_component(b, Transform(5));
+
+        {
+            //Writing the front while reading the back is fine.
+            let mut front = w.req_write_guard::<Transform>();
+            let back = w.req_read_guard_back::<Transform>();
+            front.get_mut(&a).unwrap().0 += back.get(&a).unwrap().0;
+            assert!(back.get(&b).is_none());
+        }
+        assert_eq!(w.req_read_guard::<Transform>().get(&a).unwrap().0, 3);
+
+        w.swap_buffers::<Transform>();
+        assert!(w.matches(a, &w.component_mask::<Transform>()));
+        assert!(!w.matches(b, &w.component_mask::<Transform>()));
+
+        //Dead Entities are cleared from both buffers.
+        w.rm_entity(a);
+        w.maintain_ecs();
+        assert!(!w.has_component::<Transform>(&a));
+        assert!(!w.req_read_guard_back::<Transform>().contains(&a));
+
+        w.unregister_component::<Transform>().unwrap();
+        w.register_component::<Transform>();
+    }
+
+    #[test]
+    fn try_join() {
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+        w.add_component(ent, Other);
+
+        assert_eq!(crate::try_join!(w, &TestComponent, &Other).unwrap().count(), 1);
+
+        let writing = w.req_write_guard::<Other>();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(crate::try_join!(w, &TestComponent, &Other).is_none());
+                //Any guard taken before giving up was released again.
+                assert!(w.try_req_write_guard::<TestComponent>().is_some());
+            });
+        });
+        drop(writing);
+
+        assert!(crate::try_join!(w, &mut TestComponent, &Other).is_some());
+    }
+
+    #[test]
+    fn join_mut_ref() {
+        struct Pos(i32);
+        struct Vel(i32);
+        impl Component for Pos {}
+        impl Component for Vel {}
+
+        let w = World::new();
+        w.register_component::<Pos>();
+        w.register_component::<Vel>();
+        let moving = w.create_entity();
+        let still = w.create_entity();
+        w.add_component(moving, Pos(0));
+        w.add_component(moving, Vel(3));
+        w.add_component(still, Pos(0));
+
+        w.join_mut_ref::<Pos, Vel, _>(|_, pos, vel| pos.0 += vel.0);
+
+        let guard = w.req_read_guard::<Pos>();
+        assert_eq!(guard.get(&moving).unwrap().0, 3);
+        assert_eq!(guard.get(&still).unwrap().0, 0);
+    }
+
+    #[test]
+    fn entity_limit() {
+        let w = World::with_entity_limit(3);
+        let ents = w.create_entities(2);
+        w.create_entity();
+
+        assert_eq!(
+            w.try_create_entity(),
+            Err(crate::ECSError::LimitReached { limit: 3 })
+        );
+        assert!(w.try_create_entities(1).is_err());
+
+        w.rm_entity(ents[0]);
+        assert!(w.try_create_entity().is_ok());
+        assert!(w.try_create_entity().is_err());
+
+        //Dead IDs are recycled without counting against the limit.
+        w.rm_entity(ents[1]);
+        w.maintain_ecs();
+        assert!(w.try_create_entities(2).is_err());
+        let recycled = w.try_create_entity().unwrap();
+        assert_eq!(recycled.generation(), 1);
+
+        //Reserving counts against the limit, and is refused by it, too.
+        w.rm_entity(recycled);
+        assert!(w.reserve_entity().is_ok());
+        assert_eq!(w.reserve_entity(), Err(crate::ECSError::LimitReached { limit: 3 }));
+        assert_eq!(w.entity_count(), 3);
+    }
+
+    #[test]
+    fn clone_storage() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Hp(i32);
+        impl Component for Hp {}
+
+        let w = World::new();
+        w.register_component::<Hp>();
+        let ent = w.create_entity();
+        w.add_component(ent, Hp(1));
+
+        let copy = w.clone_storage::<Hp>();
+        //The read guard is already gone, so writers aren't kept waiting.
+        w.try_req_write_guard::<Hp>().unwrap().get_mut(&ent).unwrap().0 = 2;
+
+        assert_eq!(copy.get(&ent), Some(&Hp(1)));
+        assert_eq!(w.get_cloned::<Hp>(&ent), Some(Hp(2)));
+    }
+
+    #[test]
+    fn modify() {
+        #[derive(Debug, PartialEq)]
+        enum Door {
+            Open,
+            Closed(u32),
+        }
+        impl Component for Door {}
+
+        let w = World::new();
+        w.register_component::<Door>();
+        let door = w.create_entity();
+        let wall = w.create_entity();
+        w.add_component(door, Door::Closed(3));
+
+        let mut guard = w.req_write_guard::<Door>();
+        let open = |d| match d {
+            Door::Closed(_) => Door::Open,
+            open => open,
+        };
+        assert!(guard.modify(door, open));
+        assert!(!guard.modify(wall, open));
+        drop(guard);
+
+        assert_eq!(w.req_read_guard::<Door>().get(&door), Some(&Door::Open));
+        assert!(w.matches(door, &w.component_mask::<Door>()));
+        assert_eq!(w.component_count::<Door>(), 1);
+    }
+
+    #[test]
+    fn join_sorted() {
+        struct Other;
+        impl Component for Other {}
+
+        fn run() -> (Vec<Entity>, Vec<Entity>) {
+            let w = World::new();
+            w.register_component::<TestComponent>();
+            w.register_component::<Other>();
+
+            for (i, ent) in w.create_entities(200).into_iter().enumerate() {
+                w.add_component(ent, TestComponent::default());
+                if i % 3 != 0 {
+                    w.add_component(ent, Other);
+                }
+            }
+
+            let mut joined = crate::join_sorted!(w, &TestComponent, &Other);
+            let iterated: Vec<Entity> = joined.iter().map(|(e, _, _)| e).collect();
+            let mut visited = Vec::new();
+            joined.for_each(|e, _, _| visited.push(e));
+
+            (iterated, visited)
+        }
+
+        let (iterated, visited) = run();
+        assert_eq!(iterated.len(), 133);
+        assert!(iterated.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(iterated, visited);
+        assert_eq!(run(), (iterated, visited));
+    }
+
+    #[test]
+    fn hooks() {
+        use std::sync::{Arc, Mutex};
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let inserted = Arc::clone(&log);
+        w.on_insert::<TestComponent>(move |e, _| inserted.lock().unwrap().push(("ins", e)));
+        let removed = Arc::clone(&log);
+        w.on_remove::<TestComponent>(move |e, _| removed.lock().unwrap().push(("rm", e)));
+
+        let a = w.create_entity();
+        let b = w.create_entity();
+        w.add_component(a, TestComponent::default());
+        w.add_component(a, TestComponent::default());
+        w.rm_component::<TestComponent>(&a);
+        w.rm_component::<TestComponent>(&a);
+        w.add_components([(b, TestComponent::default())]);
+        w.rm_entity(b);
+        w.maintain_ecs();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![("ins", a), ("rm", a), ("ins", a), ("rm", a), ("ins", b), ("rm", b)]
+        );
+    }
+
+    #[cfg(feature = "starvation-watchdog")]
+    #[test]
+    fn starvation_watchdog() {
+        use std::{
+            sync::{Arc, Mutex},
+            time::Duration,
+        };
+
+        struct Starved;
+        impl Component for Starved {}
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&reports);
+        crate::set_starvation_watchdog(Duration::from_millis(20), move |name, waited| {
+            if name.ends_with("Starved") {
+                reported.lock().unwrap().push(waited);
+            }
+        });
+
+        let w = World::new();
+        w.register_component::<Starved>();
+
+        let writing = w.req_write_guard::<Starved>();
+        std::thread::scope(|s| {
+            let reader = s.spawn(|| drop(w.req_read_guard::<Starved>()));
+            wait_until(|| !reports.lock().unwrap().is_empty());
+            drop(writing);
+            reader.join().unwrap();
+        });
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0] >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn dead_entity_count() {
+        let w = World::new();
+        let ents = w.create_entities(4);
+        assert_eq!(w.dead_entity_count(), 0);
+        assert!(!w.pending_maintenance());
+
+        w.rm_entity(ents[0]);
+        w.rm_entity(ents[1]);
+        assert_eq!(w.dead_entity_count(), 2);
+        assert!(w.pending_maintenance());
+
+        w.maintain_ecs();
+        assert_eq!(w.dead_entity_count(), 2);
+        assert!(!w.pending_maintenance());
+
+        w.create_entity();
+        assert_eq!(w.dead_entity_count(), 1);
+    }
+
+    #[test]
+    fn query_builder() {
+        struct Other;
+        impl Component for Other {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+        let ents = w.create_entities(3);
+        for ent in ents.iter() {
+            w.add_component(*ent, TestComponent::default());
+        }
+        w.add_component(ents[1], Other);
+
+        let counters = w.query().write::<TestComponent>().build();
+        counters.for_each(|_, (comp,)| comp._val += 1);
+        counters.for_each(|_, (comp,)| comp._val += 1);
+
+        let tagged = w.query().read::<Other>().read::<TestComponent>().build();
+        assert_eq!(tagged.count(), 1);
+        tagged.for_each(|e, (_, comp)| assert_eq!((e, comp._val), (ents[1], 2)));
+    }
+
+    #[test]
+    fn local_world() {
+        use super::local_world::LocalWorld;
+        use std::rc::Rc;
+
+        let w = LocalWorld::new();
+        w.register_component::<Rc<usize>>();
+        let shared = Rc::new(7usize);
+        let ents: Vec<Entity> = (0..3).map(|_| w.create_entity()).collect();
+        for ent in ents.iter() {
+            w.add_component(*ent, Rc::clone(&shared));
+        }
+        assert_eq!(Rc::strong_count(&shared), 4);
+
+        {
+            let reading = w.req_read_guard::<Rc<usize>>();
+            assert!(w.try_req_read_guard::<Rc<usize>>().is_some());
+            assert!(w.try_req_write_guard::<Rc<usize>>().is_none());
+            assert_eq!(reading.iter().map(|rc| **rc).sum::<usize>(), 21);
+        }
+
+        w.rm_entity(ents[0]);
+        w.maintain_ecs();
+        assert_eq!(Rc::strong_count(&shared), 3);
+        assert_eq!(w.create_entity().index(), ents[0].index());
+    }
+
+    #[test]
+    fn with_pair_mut() {
+        struct Health(i32);
+        struct Armor(i32);
+        impl Component for Health {}
+        impl Component for Armor {}
+
+        let w = World::new();
+        w.register_component::<Health>();
+        w.register_component::<Armor>();
+        let knight = w.create_entity();
+        let peasant = w.create_entity();
+        w.add_component(knight, Health(10));
+        w.add_component(knight, Armor(4));
+        w.add_component(peasant, Health(10));
+
+        let hit = |hp: &mut Health, armor: &Armor| {
+            hp.0 -= 6 - armor.0;
+            hp.0
+        };
+        assert_eq!(w.with_pair_mut(knight, hit), Some(8));
+        assert_eq!(w.with_pair_mut(peasant, hit), None);
+        assert_eq!(w.req_read_guard::<Health>().get(&peasant).unwrap().0, 10);
+    }
+
+    #[test]
+    fn recycle_policy() {
+        use super::{world::WorldConfig, RecyclePolicy};
+
+        fn reused(w: World) -> Vec<u32> {
+            let ents = w.create_entities(3);
+            for ent in ents.iter() {
+                w.rm_entity(*ent);
+            }
+            w.maintain_ecs();
+            w.create_entities(3).iter().map(|e| e.index()).collect()
+        }
+
+        //Freed in index order, so LIFO hands them back reversed.
+        assert_eq!(reused(World::new()), vec![2, 1, 0]);
+
+        let fifo = WorldConfig {
+            recycling: RecyclePolicy::Fifo,
+            ..WorldConfig::default()
+        };
+        let w = World::new_with_config(fifo);
+        w.reset(); //The policy outlives a reset.
+        assert_eq!(reused(w), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn spawn_batch() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.create_entity();
+
+        let ents = w.spawn_batch((0..5).map(|i| TestComponent { _val: i }));
+        assert_eq!(ents.len(), 5);
+        assert_eq!(w.entity_count(), 6);
+
+        let guard = w.req_read_guard::<TestComponent>();
+        for (i, ent) in ents.iter().enumerate() {
+            assert_eq!(guard.get(ent).unwrap()._val, i);
+        }
+    }
+
+    #[test]
+    fn spawn_bundles() {
+        struct Other;
+        struct Unregistered;
+        impl Component for Other {}
+        impl Component for Unregistered {}
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        w.register_component::<Other>();
+
+        let ent = w.spawn((Other, TestComponent { _val: 2 }));
+        assert_eq!(w.component_types(ent).len(), 2);
+
+        let ents = w.spawn_batch_bundle((0..3).map(|i| (TestComponent { _val: i }, Other)));
+        assert_eq!(w.count_with::<(&TestComponent, &Other)>(), 4);
+        assert_eq!(w.req_read_guard::<TestComponent>().get(&ents[2]).unwrap()._val, 2);
+
+        //An unregistered type panics before any Entity is created.
+        let spawned = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            w.spawn((Other, Unregistered));
+        }));
+        assert!(spawned.is_err());
+        assert_eq!(w.entity_count(), 4);
+    }
+
+    #[test]
+    fn is_registered() {
+        use std::any::TypeId;
+
+        let w = World::new();
+        assert!(!w.is_registered::<TestComponent>());
+
+        w.register_component::<TestComponent>();
+        assert!(w.is_registered::<TestComponent>());
+        assert!(w.is_registered_type(TypeId::of::<TestComponent>()));
+
+        w.unregister_component::<TestComponent>().unwrap();
+        assert!(!w.is_registered_type(TypeId::of::<TestComponent>()));
+    }
+
+    #[test]
+    fn index_guards() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ent = w.create_entity();
+        let bare = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+
+        let tick = w.advance_tick();
+        w.req_write_guard::<TestComponent>()[ent]._val += 5;
+        assert_eq!(w.req_read_guard::<TestComponent>()[ent]._val, 5);
+        assert_eq!(
+            w.req_read_guard::<TestComponent>().iter_changed_since(tick).count(),
+            1
+        );
+
+        let missing = std::panic::catch_unwind(|| w.req_read_guard::<TestComponent>()[bare]._val);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn run_systems() {
+        use super::{
+            scheduler::{ECSSystemError, System},
+            ECSError,
+        };
+
+        struct Despawner;
+        impl System for Despawner {
+            fn run(&mut self, world: &World) -> Result<(), ECSSystemError> {
+                world.try_rm_component::<TestComponent>(&Entity::new(0, 0))?;
+                Ok(())
+            }
+        }
+
+        let w = World::new();
+        let bump = |w: &World| {
+            w.create_entity();
+        };
+        assert!(w.run(&mut bump.clone()).is_ok());
+
+        let mut systems: Vec<Box<dyn System>> =
+            vec![Box::new(bump), Box::new(Despawner), Box::new(bump)];
+        match w.run_all(&mut systems) {
+            Err((1, ECSSystemError::ECS(ECSError::Unregistered { .. }))) => {}
+            other => panic!("expected Despawner to fail, got {:?}", other),
+        }
+        assert_eq!(w.entity_count(), 2);
+    }
+
+    #[test]
+    fn stateful_systems() {
+        use super::scheduler::Scheduler;
+        use std::any::TypeId;
+
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ent = w.create_entity();
+        w.add_component(ent, TestComponent::default());
+
+        let mut frames = 0;
+        let mut scheduler = Scheduler::new();
+        scheduler.add_system(
+            move |w: &World| {
+                frames += 1;
+                w.req_write_guard::<TestComponent>()[ent]._val = frames;
+            },
+            &[],
+            &[TypeId::of::<TestComponent>()],
+        );
+        scheduler.build().unwrap();
+
+        for _ in 0..3 {
+            scheduler.run_parallel(&w).unwrap();
+        }
+        assert_eq!(w.req_read_guard::<TestComponent>()[ent]._val, 3);
+    }
+
+    #[test]
+    fn storage_token() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        let token = unsafe { w.lock_storage_read::<TestComponent>() };
+        assert!(w.try_req_read_guard::<TestComponent>().is_some());
+
+        //Unlocked from another thread than the one that locked it.
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                assert!(w.try_req_write_guard::<TestComponent>().is_none());
+                w.unlock_storage(token);
+            });
+        });
+
+        assert!(w.try_req_write_guard::<TestComponent>().is_some());
+    }
+
+    #[test]
+    fn get_or_insert_default() {
+        #[derive(Default)]
+        struct Stats(u32);
+        impl Component for Stats {}
+
+        let w = World::new();
+        w.register_component::<Stats>();
+        let ent = w.create_entity();
+
+        assert!(w.get_or_insert_default::<Stats>(ent));
+        w.req_write_guard::<Stats>().get_mut(&ent).unwrap().0 = 5;
+        assert!(!w.get_or_insert_default::<Stats>(ent));
+        assert_eq!(w.req_read_guard::<Stats>().get(&ent).unwrap().0, 5);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn diff_and_apply() {
+        #[derive(Component, serde::Serialize, serde::Deserialize, PartialEq, Debug)]
+        struct Hp(u32);
+        struct Local;
+        impl Component for Local {}
+
+        let server = World::new();
+        let client = World::new();
+        server.register_serializable_component::<Hp>("hp");
+        client.register_serializable_component::<Hp>("hp");
+        client.register_component::<Local>();
+
+        let a = server.create_entity();
+        let b = server.create_entity();
+        server.add_component(a, Hp(10));
+        server.add_component(b, Hp(20));
+
+        let sent = server.save().unwrap();
+        client.load(&sent).unwrap();
+        client.add_component(a, Local);
+        client.add_component(b, Local);
+        assert!(server.diff(&sent).unwrap().is_empty());
+
+        server.add_component(a, Hp(5));
+        server.rm_entity(b);
+        server.maintain_ecs();
+        let c = server.create_entity();
+        server.add_component(c, Hp(30));
+
+        let delta = server.diff(&sent).unwrap();
+        let mut json = serde_json::to_value(&delta).unwrap();
+
+        //A delta that fails to deserialize leaves the client untouched.
+        let mut bad = json.clone();
+        bad["components"]["hp"]["added"][0][1] = serde_json::json!("not a number");
+        let bad: crate::WorldDelta = serde_json::from_value(bad).unwrap();
+        assert!(bad.apply(&client).is_err());
+        assert!(client.is_alive(b));
+        assert_eq!(client.req_read_guard::<Hp>().get(&a), Some(&Hp(10)));
+
+        serde_json::from_value::<crate::WorldDelta>(json.take())
+            .unwrap()
+            .apply(&client)
+            .unwrap();
+
+        assert!(client.is_alive(a) && client.is_alive(c) && !client.is_alive(b));
+        {
+            let hp = client.req_read_guard::<Hp>();
+            assert_eq!(hp.get(&a), Some(&Hp(5)));
+            assert_eq!(hp.get(&c), Some(&Hp(30)));
+            assert_eq!(hp.len(), 2);
+        }
+
+        //b's non-serializable Component went with it.
+        let local = client.req_read_guard::<Local>();
+        assert!(local.contains(&a) && local.len() == 1);
+        drop(local);
+        assert!(client.matches(a, &client.component_mask::<Local>()));
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn access_trace() {
+        use std::sync::Mutex;
+
+        struct Traced;
+        impl Component for Traced {}
+
+        static EVENTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+        struct Recorder;
+        impl log::Log for Recorder {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+            fn log(&self, record: &log::Record) {
+                let line = record.args().to_string();
+                if record.target() == "ecs_it::access" && line.contains("Traced") {
+                    EVENTS.lock().unwrap().push(line);
+                }
+            }
+            fn flush(&self) {}
+        }
+
+        log::set_logger(&Recorder).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let w = World::new();
+        w.register_component::<Traced>();
+        drop(w.req_write_guard::<Traced>());
+        drop(w.req_read_guard::<Traced>());
+
+        //Uncontended, with no writer left waiting, so the read must take the
+        //fast path.
+        let events = EVENTS.lock().unwrap();
+        let expected = [
+            "waiting for write",
+            "acquired write",
+            "released write",
+            "acquired read (fast path)",
+            "released read (fast path)",
+        ];
+        assert_eq!(events.len(), expected.len(), "{events:#?}");
+        for (event, expected) in events.iter().zip(expected) {
+            assert!(event.contains(&format!(") {expected} ")), "{event}");
+        }
+        assert!(events[1].contains("write_allowed=false"));
+    }
+
+    #[test]
+    fn hooks_on_moves_links_and_unregister() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        struct Hp;
+        struct MaxHp;
+        impl Component for Hp {}
+        impl Component for MaxHp {}
+
+        let w = World::new();
+        w.register_linked_components::<(Hp, MaxHp)>();
+        let removed = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&removed);
+        w.on_remove::<Hp>(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        let [a, b] = [w.create_entity(), w.create_entity()];
+        w.add_component(a, Hp);
+        w.add_component(a, MaxHp);
+        w.move_component::<Hp>(a, b);
+        assert_eq!(removed.load(Ordering::SeqCst), 1);
+
+        w.rm_entity(b);
+        w.maintain_ecs();
+        assert_eq!(removed.load(Ordering::SeqCst), 2);
+
+        w.unregister_component::<Hp>().unwrap();
+        w.register_component::<Hp>();
+        w.add_component(a, Hp);
+        w.rm_component::<Hp>(&a);
+        assert_eq!(removed.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn add_to_removed_entity() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+
+        let stale = w.create_entity();
+        w.rm_entity(stale);
+        w.maintain_ecs();
+        let live = w.create_entity();
+
+        assert!(w.add_component(stale, TestComponent::default()).is_none());
+        assert!(w.try_add_component(stale, TestComponent::default()).is_err());
+        assert!(!w.get_or_insert_default::<TestComponent>(stale));
+        w.add_components([(stale, TestComponent::default()), (live, TestComponent::default())]);
+        assert_eq!(w.req_read_guard::<TestComponent>().len(), 1);
+
+        //Removed but not yet maintained counts as removed too.
+        w.rm_entity(live);
+        assert!(w.add_component(live, TestComponent { _val: 1 }).is_none());
+        w.maintain_ecs();
+        assert!(w.req_read_guard::<TestComponent>().is_empty());
+    }
+
+    #[test]
+    fn rm_component() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let entity0 = w.create_entity();
+        w.add_component(entity0, TestComponent { _val: 7 });
+
+        let removed = w.rm_component::<TestComponent>(&entity0);
+        assert_eq!(removed.map(|c| c._val), Some(7));
+        assert!(w.rm_component::<TestComponent>(&entity0).is_none());
+    }
+
+    #[test]
+    fn maintain_ecs_drops_dead_components() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        struct DropCounter;
+        impl Component for DropCounter {}
+        impl Drop for DropCounter {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let w = World::new();
+        w.register_component::<DropCounter>();
+        w.register_component::<TestComponent>();
+        let entity0 = w.create_entity();
+        let entity1 = w.create_entity();
+        w.add_component(entity0, DropCounter);
+        w.add_component(entity1, DropCounter);
+        w.add_component(entity0, TestComponent::default());
+
+        w.rm_entity(entity0);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+        assert!(!w.is_alive(entity0));
+        assert!(w.has_component::<TestComponent>(&entity0));
+
+        w.maintain_ecs();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        assert!(w.req_read_guard::<TestComponent>().get(&entity0).is_none());
+        assert!(w.req_read_guard::<DropCounter>().get(&entity1).is_some());
+
+        //The recycled ID must come back without any stale Components.
+        let recycled = w.create_entity();
+        assert_eq!(recycled.index(), entity0.index());
+        assert!(w.req_read_guard::<DropCounter>().get(&recycled).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Did you forget to register a Component?")]
+    fn req_unregistered_guard() {
+        let w = World::new();
+        let _guard = w.req_read_guard::<TestComponent>();
+    }
+
+    #[test]
+    fn req_guard_checked() {
+        use super::ECSError;
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let w = World::new();
+        assert_eq!(
+            w.req_read_guard_checked::<TestComponent>().err(),
+            Some(ECSError::Unregistered {
+                type_name: std::any::type_name::<TestComponent>()
+            })
+        );
+
+        //A panicking request must not poison the World for later requests.
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            w.req_write_guard::<TestComponent>();
+        }));
+        assert!(result.is_err());
+
+        w.register_component::<TestComponent>();
+        assert!(w.req_write_guard_checked::<TestComponent>().is_ok());
+    }
+
+    #[test]
+    fn panic_while_guarded() {
+        let w = World::new();
+        w.register_component::<TestComponent>();
+        let ent = w.create_entity();
+
+        std::thread::scope(|s| {
+            let writer = s.spawn(|| {
+                let mut guard = w.req_write_guard::<TestComponent>();
+                guard.insert(ent, TestComponent::default());
+                panic!("System failed mid-write");
+            });
+            assert!(writer.join().is_err());
+
+            let reader = s.spawn(|| {
+                let _guard = w.req_read_guard::<TestComponent>();
+                panic!("System failed mid-read");
+            });
+            assert!(reader.join().is_err());
+        });
+
+        assert!(w.req_write_guard::<TestComponent>().remove(&ent).is_some());
+        assert!(w.req_read_guard::<TestComponent>().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "unregistered component storage")]
+    fn add_unregistered_component() {
+        let w = World::new();
+        let entity0 = w.create_entity();
+        w.add_component(entity0, TestComponent::default());
+    }
 }
@@ -0,0 +1,310 @@
+//-----------------------------------------------------------------------------
+//----------------- Single-Threaded World for non-Send Components -------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::{hash_map::Entry, HashMap},
+    sync::atomic::AtomicU32,
+};
+
+use super::{entity::Entities, Entity};
+
+///A World for use on a single thread, whose Components need not be Send or
+///Sync; e.g. ones wrapping an Rc or a raw pointer, which Component forbids.
+///
+///Storages are tracked RefCell-style rather than with World's condvars:
+///requesting a guard never blocks, it panics if that would conflict with a
+///guard that still exists (or returns None, for the try_ requests). The
+///guards mirror the StorageGuard API, so Systems written against
+///get()/get_mut()/insert()/iter() and so on port between the two.
+///
+///LocalWorld is neither Sync nor Send, so it can never be shared with, or
+///moved to, another thread.
+///
+///# Example
+///```
+/// use std::rc::Rc;
+/// use ecs_it::local_world::LocalWorld;
+///
+/// struct Sprite(Rc<String>);
+///
+/// let world = LocalWorld::new();
+/// world.register_component::<Sprite>();
+///
+/// let ent = world.create_entity();
+/// world.add_component(ent, Sprite(Rc::new("hero.png".to_string())));
+///
+/// assert_eq!(*world.req_read_guard::<Sprite>().get(&ent).unwrap().0, "hero.png");
+///```
+pub struct LocalWorld {
+    entities: RefCell<Entities>,
+    fresh_indices: AtomicU32, //next never-used Entity index
+    storages: RefCell<HashMap<TypeId, LocalStorage>>,
+}
+
+struct LocalStorage {
+    boxed: Box<dyn Any>, //RefCell<HashMap<Entity, T>>
+    remove: fn(&LocalWorld, &[Entity]),
+}
+
+impl Default for LocalWorld {
+    fn default() -> Self {
+        LocalWorld::new()
+    }
+}
+
+impl LocalWorld {
+    pub fn new() -> Self {
+        LocalWorld {
+            entities: RefCell::new(Entities::new()),
+            fresh_indices: AtomicU32::new(0),
+            storages: RefCell::new(HashMap::new()),
+        }
+    }
+
+    ///Like World::create_entity().
+    pub fn create_entity(&self) -> Entity {
+        self.entities
+            .borrow_mut()
+            .new_entity_id(&self.fresh_indices)
+    }
+
+    ///Like World::rm_entity(): the Entity's Components are dropped, and its
+    ///ID freed for reuse, at the next maintain_ecs().
+    pub fn rm_entity(&self, e: Entity) {
+        self.entities.borrow_mut().rm_entity(e);
+    }
+
+    ///Like World::is_alive().
+    pub fn is_alive(&self, e: Entity) -> bool {
+        self.entities.borrow().is_alive(&e)
+    }
+
+    ///Like World::entity_count().
+    pub fn entity_count(&self) -> usize {
+        self.entities.borrow().living_count()
+    }
+
+    ///Like World::maintain_ecs().
+    ///## Panics
+    ///Panics if any guard over a Storage still exists.
+    pub fn maintain_ecs(&self) {
+        let dead = self.entities.borrow_mut().take_unmaintained();
+
+        if dead.is_empty() {
+            return;
+        }
+
+        let removes: Vec<fn(&LocalWorld, &[Entity])> = self
+            .storages
+            .borrow()
+            .values()
+            .map(|storage| storage.remove)
+            .collect();
+
+        for remove in removes {
+            remove(self, &dead);
+        }
+
+        self.entities.borrow_mut().recycle(dead);
+    }
+
+    ///Like World::register_component(), but T need only be 'static.
+    ///## Panics
+    ///Panics if you register the same component type twice.
+    pub fn register_component<T: 'static>(&self) {
+        fn remove<T: 'static>(world: &LocalWorld, dead: &[Entity]) {
+            let mut guard = world.req_write_guard::<T>();
+            for e in dead {
+                guard.remove(e);
+            }
+        }
+
+        match self.storages.borrow_mut().entry(TypeId::of::<T>()) {
+            Entry::Occupied(_) => panic!("attempted to register the same component type twice"),
+            Entry::Vacant(slot) => {
+                slot.insert(LocalStorage {
+                    boxed: Box::new(RefCell::new(HashMap::<Entity, T>::new())),
+                    remove: remove::<T>,
+                });
+            }
+        }
+    }
+
+    ///Like World::add_component().
+    ///## Panics
+    ///Panics if T is unregistered or any guard over its Storage exists.
+    pub fn add_component<T: 'static>(&self, ent: Entity, comp: T) -> Option<T> {
+        self.req_write_guard::<T>().insert(ent, comp)
+    }
+
+    ///Like World::rm_component().
+    ///## Panics
+    ///Panics if T is unregistered or any guard over its Storage exists.
+    pub fn rm_component<T: 'static>(&self, ent: &Entity) -> Option<T> {
+        self.req_write_guard::<T>().remove(ent)
+    }
+
+    ///Like World::req_read_guard(), but never blocks.
+    ///## Panics
+    ///Panics if T is unregistered, or if a write guard over its Storage exists.
+    pub fn req_read_guard<T: 'static>(&self) -> LocalReadGuard<'_, T> {
+        self.try_req_read_guard::<T>().unwrap_or_else(|| {
+            panic!(
+                "requested read access to {} while a write guard over it exists",
+                type_name::<T>()
+            )
+        })
+    }
+
+    ///Like World::req_write_guard(), but never blocks.
+    ///## Panics
+    ///Panics if T is unregistered, or if any guard over its Storage exists.
+    pub fn req_write_guard<T: 'static>(&self) -> LocalWriteGuard<'_, T> {
+        self.try_req_write_guard::<T>().unwrap_or_else(|| {
+            panic!(
+                "requested write access to {} while a guard over it exists",
+                type_name::<T>()
+            )
+        })
+    }
+
+    ///Returns None if a write guard over the Storage of T exists.
+    ///## Panics
+    ///Panics if T is unregistered.
+    pub fn try_req_read_guard<T: 'static>(&self) -> Option<LocalReadGuard<'_, T>> {
+        Some(LocalReadGuard {
+            inner: self.storage::<T>().try_borrow().ok()?,
+        })
+    }
+
+    ///Returns None if any guard over the Storage of T exists.
+    ///## Panics
+    ///Panics if T is unregistered.
+    pub fn try_req_write_guard<T: 'static>(&self) -> Option<LocalWriteGuard<'_, T>> {
+        Some(LocalWriteGuard {
+            inner: self.storage::<T>().try_borrow_mut().ok()?,
+        })
+    }
+
+    fn storage<T: 'static>(&self) -> &RefCell<HashMap<Entity, T>> {
+        let storages = self.storages.borrow();
+
+        let storage: *const RefCell<HashMap<Entity, T>> = storages
+            .get(&TypeId::of::<T>())
+            .unwrap_or_else(|| {
+                panic!("attempted to access unregistered component type {}", type_name::<T>())
+            })
+            .boxed
+            .downcast_ref::<RefCell<HashMap<Entity, T>>>()
+            .expect("local storage stored under the wrong TypeId");
+
+        //Storages are boxed, and only ever dropped along with self, so the
+        //RefCell outlives the borrow of the map that found it.
+        unsafe { &*storage }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+///What you get from LocalWorld::req_read_guard(); mirrors the read-only part
+///of ImmutableStorageGuard.
+pub struct LocalReadGuard<'w, T> {
+    inner: Ref<'w, HashMap<Entity, T>>,
+}
+
+impl<T> LocalReadGuard<'_, T> {
+    pub fn get(&self, e: &Entity) -> Option<&T> {
+        self.inner.get(e)
+    }
+
+    pub fn contains(&self, e: &Entity) -> bool {
+        self.inner.contains_key(e)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.inner.values()
+    }
+
+    pub fn iter_entities(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.inner.iter().map(|(e, c)| (*e, c))
+    }
+
+    pub fn raw(&self) -> &HashMap<Entity, T> {
+        &self.inner
+    }
+}
+
+///What you get from LocalWorld::req_write_guard(); mirrors the
+///MutableStorageGuard API, minus change detection and ComponentMasks.
+pub struct LocalWriteGuard<'w, T> {
+    inner: RefMut<'w, HashMap<Entity, T>>,
+}
+
+impl<T> LocalWriteGuard<'_, T> {
+    pub fn get(&self, e: &Entity) -> Option<&T> {
+        self.inner.get(e)
+    }
+
+    pub fn get_mut(&mut self, e: &Entity) -> Option<&mut T> {
+        self.inner.get_mut(e)
+    }
+
+    pub fn contains(&self, e: &Entity) -> bool {
+        self.inner.contains_key(e)
+    }
+
+    pub fn entry(&mut self, e: Entity) -> Entry<'_, Entity, T> {
+        self.inner.entry(e)
+    }
+
+    pub fn insert(&mut self, e: Entity, c: T) -> Option<T> {
+        self.inner.insert(e, c)
+    }
+
+    pub fn remove(&mut self, e: &Entity) -> Option<T> {
+        self.inner.remove(e)
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.inner.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.inner.values_mut()
+    }
+
+    pub fn iter_entities(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.inner.iter().map(|(e, c)| (*e, c))
+    }
+
+    pub fn iter_entities_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.inner.iter_mut().map(|(e, c)| (*e, c))
+    }
+
+    pub fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    pub fn raw(&self) -> &HashMap<Entity, T> {
+        &self.inner
+    }
+}
@@ -0,0 +1,140 @@
+//-----------------------------------------------------------------------------
+//-------------------- Typed Query Builder Over Join --------------------------
+//-----------------------------------------------------------------------------
+
+use std::marker::PhantomData;
+
+use super::{
+    join::{Fetch, Join, JoinQuery},
+    world::World,
+    Component, Entity,
+};
+
+///Names the Storages of a Query, one read::<T>() or write::<T>() at a time,
+///for up to 4 Component types. Start one via World::query().
+pub struct QueryBuilder<'w, Q> {
+    world: &'w World,
+    query: PhantomData<Q>,
+}
+
+///A reusable Join over the Storages named by a QueryBuilder. Holds no guards
+///itself: each call acquires them, in ascending TypeId order like any other
+///Join, and drops them again before returning.
+///
+///# Example
+///```
+/// use ecs_it::*;
+///
+/// struct Pos(i32);
+/// struct Vel(i32);
+/// impl Component for Pos {}
+/// impl Component for Vel {}
+///
+/// let world = world::World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Vel>();
+///
+/// let ent = world.create_entity();
+/// world.add_component(ent, Pos(0));
+/// world.add_component(ent, Vel(3));
+///
+/// let movement = world.query().write::<Pos>().read::<Vel>().build();
+/// movement.for_each(|_ent, (pos, vel)| pos.0 += vel.0);
+/// movement.for_each(|_ent, (pos, vel)| pos.0 += vel.0);
+///
+/// assert_eq!(world.req_read_guard::<Pos>().get(&ent).unwrap().0, 6);
+///```
+pub struct Query<'w, Q> {
+    world: &'w World,
+    query: PhantomData<Q>,
+}
+
+impl World {
+    ///Starts building a Query; see QueryBuilder.
+    pub fn query(&self) -> QueryBuilder<'_, ()> {
+        QueryBuilder {
+            world: self,
+            query: PhantomData,
+        }
+    }
+}
+
+macro_rules! impl_query_builder {
+    ($($t:ident),*) => {
+        impl<'w, $($t: Fetch),*> QueryBuilder<'w, ($($t,)*)> {
+            ///Adds read access to the Storage of T.
+            pub fn read<T: Component>(self) -> QueryBuilder<'w, ($($t,)* &'static T,)> {
+                QueryBuilder {
+                    world: self.world,
+                    query: PhantomData,
+                }
+            }
+
+            ///Adds write access to the Storage of T.
+            pub fn write<T: Component>(self) -> QueryBuilder<'w, ($($t,)* &'static mut T,)> {
+                QueryBuilder {
+                    world: self.world,
+                    query: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+impl_query_builder!();
+impl_query_builder!(A);
+impl_query_builder!(A, B);
+impl_query_builder!(A, B, C);
+
+impl<'w, Q: JoinQuery> QueryBuilder<'w, Q> {
+    ///Finishes the Query. Nothing is checked or acquired until it is run.
+    pub fn build(self) -> Query<'w, Q> {
+        Query {
+            world: self.world,
+            query: PhantomData,
+        }
+    }
+}
+
+impl<Q: JoinQuery> Query<'_, Q> {
+    ///Acquires the guards and hands them over as a Join, for iter() and the
+    ///like; they are held until the Join is dropped.
+    ///## Panics
+    ///Panics if any Component is unregistered, or if the same Component was
+    ///named more than once where any of those is a write::<T>().
+    pub fn join(&self) -> Join<Q> {
+        Join::new(self.world)
+    }
+
+    ///Number of Entities having every Component in the Query.
+    ///## Panics
+    ///Panics if any Component is unregistered, or if the same Component was
+    ///named more than once where any of those is a write::<T>().
+    pub fn count(&self) -> usize {
+        self.join().count()
+    }
+}
+
+macro_rules! impl_query {
+    ($($t:ident $v:ident),+) => {
+        impl<$($t: Fetch),+> Query<'_, ($($t,)+)> {
+            ///Calls f for every Entity having every Component in the Query,
+            ///passing them as a tuple in the order they were named.
+            ///## Panics
+            ///Panics if any Component is unregistered, or if the same
+            ///Component was named more than once where any of those is a
+            ///write::<T>().
+            pub fn for_each<Func>(&self, mut f: Func)
+            where
+                Func: for<'a> FnMut(Entity, ($($t::Item<'a>,)+)),
+            {
+                self.join().for_each(|e, $($v),+| f(e, ($($v,)+)));
+            }
+        }
+    };
+}
+
+impl_query!(A a);
+impl_query!(A a, B b);
+impl_query!(A a, B b, C c);
+impl_query!(A a, B b, C c, D d);
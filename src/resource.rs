@@ -0,0 +1,150 @@
+//-----------------------------------------------------------------------------
+//------------------- Singleton Resources Not Tied to Entities ----------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::UnsafeCell,
+    mem,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+use super::{
+    storage::{AccessPriority, Accessor, ReadAccess},
+    world::World,
+};
+
+const RESOURCE_POISON: &str = "resources mtx found poisoned in resource.rs";
+
+///A single value of type T guarded by the same reader/writer Accessor that
+///guards Storages.
+pub(crate) struct Resource<T> {
+    accessor: Accessor,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<T> Sync for Resource<T> where T: Send + Sync {}
+
+impl World {
+    ///Inserts a Resource, a piece of global data such as a game clock or an
+    ///RNG which belongs to no Entity. Only one Resource of each type exists;
+    ///if one was already inserted it is replaced and returned, blocking until
+    ///any guards over it have dropped.
+    pub fn insert_resource<T: 'static + Send + Sync>(&self, res: T) -> Option<T> {
+        let mut resources = self.resources.lock().expect(RESOURCE_POISON);
+
+        match resources.get(&TypeId::of::<T>()) {
+            Some(existing) => {
+                let existing = downcast::<T>(existing);
+                drop(resources);
+
+                let mut guard = ResourceWriteGuard::new(existing);
+                Some(mem::replace(&mut *guard, res))
+            }
+            None => {
+                let resource = Resource {
+                    accessor: Accessor::new(AccessPriority::default(), type_name::<T>()),
+                    inner: UnsafeCell::new(res),
+                };
+                resources.insert(TypeId::of::<T>(), Arc::new(resource));
+                None
+            }
+        }
+    }
+
+    ///Blocks until read access to the Resource of type T is granted.
+    ///## Panics
+    ///Panics if no Resource of type T was inserted.
+    pub fn get_resource<T: 'static + Send + Sync>(&self) -> ResourceReadGuard<T> {
+        ResourceReadGuard::new(self.resource::<T>())
+    }
+
+    ///Blocks until write access to the Resource of type T is granted.
+    ///## Panics
+    ///Panics if no Resource of type T was inserted.
+    pub fn get_resource_mut<T: 'static + Send + Sync>(&self) -> ResourceWriteGuard<T> {
+        ResourceWriteGuard::new(self.resource::<T>())
+    }
+
+    fn resource<T: 'static + Send + Sync>(&self) -> Arc<Resource<T>> {
+        let resources = self.resources.lock().expect(RESOURCE_POISON);
+
+        match resources.get(&TypeId::of::<T>()) {
+            Some(res) => downcast(res),
+            None => {
+                drop(resources);
+                panic!(
+                    "Attempted to access missing resource: {}. Did you forget to insert it?",
+                    type_name::<T>()
+                );
+            }
+        }
+    }
+}
+
+fn downcast<T: 'static + Send + Sync>(res: &Arc<dyn Any + Send + Sync>) -> Arc<Resource<T>> {
+    Arc::clone(res)
+        .downcast::<Resource<T>>()
+        .expect("resource stored under the wrong TypeId")
+}
+
+///Shared access to a Resource until dropped; derefs to T.
+pub struct ResourceReadGuard<T: 'static + Send + Sync> {
+    guarded: Arc<Resource<T>>,
+    access: ReadAccess,
+}
+
+impl<T: 'static + Send + Sync> ResourceReadGuard<T> {
+    fn new(guarded: Arc<Resource<T>>) -> Self {
+        let access = guarded.accessor.init_read_access();
+        ResourceReadGuard { guarded, access }
+    }
+}
+
+impl<T: 'static + Send + Sync> Deref for ResourceReadGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.guarded.inner.get() }
+    }
+}
+
+impl<T: 'static + Send + Sync> Drop for ResourceReadGuard<T> {
+    fn drop(&mut self) {
+        self.guarded.accessor.drop_read_access(self.access);
+    }
+}
+
+///Exclusive access to a Resource until dropped; derefs mutably to T.
+pub struct ResourceWriteGuard<T: 'static + Send + Sync> {
+    guarded: Arc<Resource<T>>,
+}
+
+impl<T: 'static + Send + Sync> ResourceWriteGuard<T> {
+    fn new(guarded: Arc<Resource<T>>) -> Self {
+        guarded.accessor.init_write_access();
+        ResourceWriteGuard { guarded }
+    }
+}
+
+impl<T: 'static + Send + Sync> Deref for ResourceWriteGuard<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.guarded.inner.get() }
+    }
+}
+
+impl<T: 'static + Send + Sync> DerefMut for ResourceWriteGuard<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        //Exclusivity is guaranteed by the Accessor.
+        unsafe { &mut *self.guarded.inner.get() }
+    }
+}
+
+impl<T: 'static + Send + Sync> Drop for ResourceWriteGuard<T> {
+    fn drop(&mut self) {
+        self.guarded.accessor.drop_write_access();
+    }
+}
@@ -0,0 +1,424 @@
+//-----------------------------------------------------------------------------
+//------------------- Runs Systems in Parallel Where Possible -----------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::TypeId,
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    fmt, panic, thread,
+};
+
+use super::{error::ECSError, world::World};
+
+///Logic that operates over one or more Storages. Implemented for every
+///`FnMut(&World) + Send` closure, so most systems need no struct at all;
+///such closures always succeed. Implement it by hand to report failures.
+///
+///run() takes &mut self, so a System may keep state, such as a frame counter
+///or cached buffers, from one tick to the next.
+pub trait System: Send {
+    fn run(&mut self, world: &World) -> Result<(), ECSSystemError>;
+}
+
+impl<F> System for F
+where
+    F: FnMut(&World) + Send,
+{
+    fn run(&mut self, world: &World) -> Result<(), ECSSystemError> {
+        self(world);
+        Ok(())
+    }
+}
+
+///Returned by a System that couldn't do its work. ECSErrors convert into it,
+///so Systems can use `?` on the World's try_ and _checked methods.
+#[derive(Debug)]
+pub enum ECSSystemError {
+    ///An ECS operation the System relied on failed.
+    ECS(ECSError),
+    ///Anything else, as described by the System.
+    Other(Box<dyn Error + Send + Sync>),
+}
+
+impl fmt::Display for ECSSystemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ECSSystemError::ECS(e) => write!(f, "ECSSystemError: {}", e),
+            ECSSystemError::Other(e) => write!(f, "ECSSystemError: {}", e),
+        }
+    }
+}
+
+impl Error for ECSSystemError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ECSSystemError::ECS(e) => Some(e),
+            ECSSystemError::Other(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+impl From<ECSError> for ECSSystemError {
+    fn from(e: ECSError) -> Self {
+        ECSSystemError::ECS(e)
+    }
+}
+
+impl World {
+    ///Runs a single System on this World, on the calling thread.
+    pub fn run<S: System + ?Sized>(&self, system: &mut S) -> Result<(), ECSSystemError> {
+        system.run(self)
+    }
+
+    ///Runs each System in turn, on the calling thread, stopping at the first
+    ///one to fail. Returns the index of that System along with its error.
+    pub fn run_all(
+        &self,
+        systems: &mut [Box<dyn System>],
+    ) -> Result<(), (usize, ECSSystemError)> {
+        for (idx, system) in systems.iter_mut().enumerate() {
+            system.run(self).map_err(|e| (idx, e))?;
+        }
+
+        Ok(())
+    }
+}
+
+///Names a System so that others can be ordered after it; see
+///Scheduler::add_system_after().
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SystemLabel(pub &'static str);
+
+///Returned by Scheduler::build() when the declared ordering can't be met.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchedulerError {
+    ///Two Systems were given the same label.
+    DuplicateLabel(SystemLabel),
+    ///A System was ordered after a label no System has.
+    UnknownLabel(SystemLabel),
+    ///The ordering constraints form a cycle through these Systems, listed in
+    ///the order they were added. If there are several cycles, this is one of
+    ///them; Systems merely ordered after a cycle aren't listed.
+    Cycle(Vec<SystemLabel>),
+}
+
+impl fmt::Display for SchedulerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchedulerError::DuplicateLabel(label) => {
+                write!(f, "SchedulerError: duplicate system label {}", label.0)
+            }
+            SchedulerError::UnknownLabel(label) => {
+                write!(f, "SchedulerError: unknown system label {}", label.0)
+            }
+            SchedulerError::Cycle(labels) => {
+                let names: Vec<&str> = labels.iter().map(|label| label.0).collect();
+                write!(f, "SchedulerError: ordering cycle among {}", names.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for SchedulerError {}
+
+struct SystemEntry {
+    system: Box<dyn System>,
+    reads: Vec<TypeId>,
+    writes: Vec<TypeId>,
+    label: Option<SystemLabel>,
+    after: Vec<SystemLabel>,
+}
+
+impl SystemEntry {
+    ///Two systems conflict if either writes a Storage the other touches.
+    fn conflicts_with(&self, other: &SystemEntry) -> bool {
+        self.writes
+            .iter()
+            .any(|t| other.reads.contains(t) || other.writes.contains(t))
+            || other.writes.iter().any(|t| self.reads.contains(t))
+    }
+}
+
+///Holds Systems along with the Storages each one reads and writes, and runs
+///them so that Systems touching disjoint sets of Storages run concurrently.
+///
+///Systems that conflict (one writes a Storage the other reads or writes) are
+///never run at the same time, and run in the order they were added unless
+///add_system_after() says otherwise. Call build() after adding Systems and
+///before running them; it orders the Systems and reports bad orderings. The
+///declared sets are trusted: a System that touches an undeclared Storage
+///still gets correct (blocking) access, but may serialize or deadlock with
+///Systems it was expected to run alongside.
+///
+///# Example
+///```
+/// use ecs_it::{scheduler::Scheduler, world::World, Component};
+/// use std::any::TypeId;
+///
+/// #[derive(Component)]
+/// struct Pos(i32);
+/// #[derive(Component)]
+/// struct Vel(i32);
+///
+/// let world = World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Vel>();
+/// let ent = world.create_entity();
+/// world.add_component(ent, Pos(0));
+/// world.add_component(ent, Vel(0));
+///
+/// let mut scheduler = Scheduler::new();
+/// scheduler.add_system(
+///     move |w: &World| w.req_write_guard::<Vel>().get_mut(&ent).unwrap().0 = 2,
+///     &[],
+///     &[TypeId::of::<Vel>()],
+/// );
+/// scheduler.add_system(
+///     move |w: &World| {
+///         let vel = w.req_read_guard::<Vel>().get(&ent).unwrap().0;
+///         w.req_write_guard::<Pos>().get_mut(&ent).unwrap().0 += vel;
+///     },
+///     &[TypeId::of::<Vel>()],
+///     &[TypeId::of::<Pos>()],
+/// );
+///
+/// scheduler.build().unwrap();
+/// scheduler.run_parallel(&world).unwrap();
+/// assert_eq!(world.req_read_guard::<Pos>().get(&ent).unwrap().0, 2);
+///```
+#[derive(Default)]
+pub struct Scheduler {
+    systems: Vec<SystemEntry>,
+    stages: Option<Vec<Vec<usize>>>, //None until built, or since last add
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler::default()
+    }
+
+    ///Adds a System which reads the Storages whose TypeIds are in reads and
+    ///writes those in writes.
+    pub fn add_system<S>(&mut self, system: S, reads: &[TypeId], writes: &[TypeId])
+    where
+        S: System + 'static,
+    {
+        self.push(system, reads, writes, None, &[]);
+    }
+
+    ///Like add_system(), but names the System so others can be ordered after it.
+    pub fn add_labeled_system<S>(
+        &mut self,
+        label: SystemLabel,
+        system: S,
+        reads: &[TypeId],
+        writes: &[TypeId],
+    ) where
+        S: System + 'static,
+    {
+        self.push(system, reads, writes, Some(label), &[]);
+    }
+
+    ///Like add_system(), but the System only starts once every System with a
+    ///label in after has finished, even if they don't conflict. Pass a label
+    ///to have other Systems ordered after this one in turn.
+    pub fn add_system_after<S>(
+        &mut self,
+        label: Option<SystemLabel>,
+        system: S,
+        reads: &[TypeId],
+        writes: &[TypeId],
+        after: &[SystemLabel],
+    ) where
+        S: System + 'static,
+    {
+        self.push(system, reads, writes, label, after);
+    }
+
+    fn push<S>(
+        &mut self,
+        system: S,
+        reads: &[TypeId],
+        writes: &[TypeId],
+        label: Option<SystemLabel>,
+        after: &[SystemLabel],
+    ) where
+        S: System + 'static,
+    {
+        self.systems.push(SystemEntry {
+            system: Box::new(system),
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            label,
+            after: after.to_vec(),
+        });
+        self.stages = None;
+    }
+
+    ///Sorts the Systems by their ordering constraints and groups them into
+    ///stages for run_parallel(). Must be called again after adding Systems.
+    pub fn build(&mut self) -> Result<(), SchedulerError> {
+        let order = self.topological_order()?;
+        self.stages = Some(self.stages(&order));
+        Ok(())
+    }
+
+    ///Runs every System once, blocking until all have finished. Systems are
+    ///grouped into stages of mutually non-conflicting Systems; each stage runs
+    ///on scoped threads, and the next stage starts once it completes.
+    ///
+    ///If a System fails, the rest of its stage still finishes, but no later
+    ///stage starts; the first error in the stage's order is returned.
+    ///## Panics
+    ///Panics if any System panics, or if build() hasn't succeeded since the
+    ///last System was added.
+    pub fn run_parallel(&mut self, world: &World) -> Result<(), ECSSystemError> {
+        let stages = self
+            .stages
+            .as_ref()
+            .expect("Scheduler::build() must succeed before run_parallel()");
+
+        for stage in stages {
+            //Each System is in exactly one stage, so each gets its own &mut.
+            let mut entries: Vec<Option<&mut SystemEntry>> =
+                self.systems.iter_mut().map(Some).collect();
+            let mut staged = stage
+                .iter()
+                .map(|&idx| entries[idx].take().expect("System placed in a stage twice"));
+
+            let lead = staged.next().expect("empty Scheduler stage");
+
+            thread::scope(|s| {
+                let handles: Vec<_> = staged
+                    .map(|entry| s.spawn(move || entry.system.run(world)))
+                    .collect();
+
+                //Use the calling thread rather than leaving it idle.
+                let first = lead.system.run(world);
+
+                handles.into_iter().fold(first, |result, handle| {
+                    let ran = handle.join().unwrap_or_else(|panic| panic::resume_unwind(panic));
+                    result.and(ran)
+                })
+            })?;
+        }
+
+        Ok(())
+    }
+
+    ///Kahn's algorithm over the after-constraints. Among Systems that are
+    ///ready at the same time, the one added first goes first, so without any
+    ///constraints this is simply insertion order.
+    fn topological_order(&self) -> Result<Vec<usize>, SchedulerError> {
+        let mut labeled: HashMap<SystemLabel, usize> = HashMap::new();
+        for (idx, entry) in self.systems.iter().enumerate() {
+            if let Some(label) = entry.label {
+                if labeled.insert(label, idx).is_some() {
+                    return Err(SchedulerError::DuplicateLabel(label));
+                }
+            }
+        }
+
+        let mut blockers: Vec<usize> = vec![0; self.systems.len()];
+        let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); self.systems.len()];
+        for (idx, entry) in self.systems.iter().enumerate() {
+            for label in entry.after.iter() {
+                let dep = *labeled
+                    .get(label)
+                    .ok_or(SchedulerError::UnknownLabel(*label))?;
+                dependents[dep].push(idx);
+                blockers[idx] += 1;
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..self.systems.len())
+            .filter(|&idx| blockers[idx] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(self.systems.len());
+
+        while let Some(idx) = ready.pop_first() {
+            order.push(idx);
+            for &dependent in dependents[idx].iter() {
+                blockers[dependent] -= 1;
+                if blockers[dependent] == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+
+        if order.len() < self.systems.len() {
+            return Err(SchedulerError::Cycle(self.find_cycle(&labeled, &blockers)));
+        }
+
+        Ok(order)
+    }
+
+    ///One cycle among the Systems topological_order() couldn't place, i.e.
+    ///those with blockers left. Each of them is ordered after at least one
+    ///other such System, so walking back through those must come round to a
+    ///System already walked; the Systems walked since then are the cycle.
+    fn find_cycle(
+        &self,
+        labeled: &HashMap<SystemLabel, usize>,
+        blockers: &[usize],
+    ) -> Vec<SystemLabel> {
+        let blocked = |idx: &usize| blockers[*idx] > 0;
+
+        let mut walked: Vec<usize> = Vec::new();
+        let mut idx = (0..self.systems.len())
+            .find(blocked)
+            .expect("no System left blocked");
+        while !walked.contains(&idx) {
+            walked.push(idx);
+            idx = self.systems[idx]
+                .after
+                .iter()
+                .map(|label| labeled[label])
+                .find(blocked)
+                .expect("blocked System waits on no blocked System");
+        }
+
+        let start = walked.iter().position(|&seen| seen == idx).unwrap_or(0);
+        let mut cycle = walked.split_off(start);
+        cycle.sort_unstable();
+
+        //Only labeled Systems can be depended upon, so every System in a
+        //cycle has a label.
+        cycle
+            .into_iter()
+            .filter_map(|idx| self.systems[idx].label)
+            .collect()
+    }
+
+    ///Places each System in the stage after the last one holding a System it
+    ///must follow: one it conflicts with that comes earlier in order, or one
+    ///it was explicitly ordered after.
+    fn stages(&self, order: &[usize]) -> Vec<Vec<usize>> {
+        let mut stage_of: HashMap<usize, usize> = HashMap::new();
+        let mut stages: Vec<Vec<usize>> = Vec::new();
+
+        for (pos, &idx) in order.iter().enumerate() {
+            let entry = &self.systems[idx];
+
+            let stage = order[..pos]
+                .iter()
+                .filter(|&&earlier| {
+                    let earlier = &self.systems[earlier];
+                    earlier.conflicts_with(entry)
+                        || earlier.label.is_some_and(|label| entry.after.contains(&label))
+                })
+                .map(|earlier| stage_of[earlier] + 1)
+                .max()
+                .unwrap_or(0);
+
+            if stage == stages.len() {
+                stages.push(Vec::new());
+            }
+            stages[stage].push(idx);
+            stage_of.insert(idx, stage);
+        }
+
+        stages
+    }
+}
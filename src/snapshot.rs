@@ -0,0 +1,352 @@
+//-----------------------------------------------------------------------------
+//---------------------- Whole-World Save/Load via Serde ----------------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::{BTreeMap, HashMap},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{entity::Entities, error::ECSError, world::World, Component, Entity};
+
+pub(crate) const SERDE_FN_POISON: &str = "serde_fns mtx found poisoned in snapshot.rs";
+
+///A point-in-time copy of every Entity and every serializable Component in a
+///World. Produced by World::save() and consumed by World::load(); it is itself
+///Serialize + Deserialize, so write it out with whatever serde format you like.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SerializedWorld {
+    entities: Entities,
+    components: BTreeMap<String, Vec<(Entity, Value)>>,
+}
+
+///What changed between a SerializedWorld and a later state of a World, as
+///returned by World::diff(). Like SerializedWorld it is Serialize +
+///Deserialize, so it can be sent over the wire and applied on the far side
+///via WorldDelta::apply().
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorldDelta {
+    entities: Option<Entities>, //None if unchanged
+    components: BTreeMap<String, ComponentDelta>,
+}
+
+///Changes to the Components of one serializable type, keyed by its name.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ComponentDelta {
+    added: Vec<(Entity, Value)>,
+    changed: Vec<(Entity, Value)>,
+    removed: Vec<Entity>,
+}
+
+impl ComponentDelta {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+type SaveFn = fn(&World) -> Result<Vec<(Entity, Value)>, ECSError>;
+type DecodeFn = fn(&[(Entity, Value)]) -> Result<Box<dyn Any>, ECSError>;
+type CommitFn = fn(&World, &[Entity], Box<dyn Any>);
+
+///One type's deserialized Components, ready for its commit fn.
+type Decoded = (SerdeFns, Box<dyn Any>);
+
+///Per-type save and load logic, stored in World at registration. Loading is
+///split in two, so that every type can be deserialized before any Storage is
+///touched: decode() turns saved values into a boxed Vec<(Entity, T)>, and
+///commit() removes the given Entities' Components then inserts those.
+#[derive(Clone, Copy)]
+pub(crate) struct SerdeFns {
+    pub(crate) type_id: TypeId, //so World::unregister_component() can find it
+    save: SaveFn,
+    decode: DecodeFn,
+    commit: CommitFn,
+}
+
+impl World {
+    ///Registers T just like register_component(), and also opts it into
+    ///World::save() and World::load(). The name identifies T's Components in
+    ///a SerializedWorld, so keep it stable across versions of your game.
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type or name twice.
+    pub fn register_serializable_component<T>(&self, name: &'static str)
+    where
+        T: Component + Serialize + DeserializeOwned,
+    {
+        let mut serde_fns = self.serde_fns.lock().expect(SERDE_FN_POISON);
+
+        if serde_fns.contains_key(name) {
+            panic!("attempted to register the same serializable component name twice");
+        }
+
+        self.register_component::<T>();
+
+        serde_fns.insert(
+            name,
+            SerdeFns {
+                type_id: TypeId::of::<T>(),
+                save: save_storage::<T>,
+                decode: decode_storage::<T>,
+                commit: commit_storage::<T>,
+            },
+        );
+    }
+
+    ///Snapshots every Entity (living, dead, and awaiting maintenance) and every
+    ///Component registered via register_serializable_component(). Components
+    ///of other types are not saved.
+    ///
+    ///Storages are read one at a time, so call this while no other thread is
+    ///mutating the World, else the snapshot may be inconsistent.
+    pub fn save(&self) -> Result<SerializedWorld, ECSError> {
+        let entities = self.lock_entities().clone();
+
+        //Copied out, so that no lock is held while waiting on Storages.
+        let serde_fns: Vec<(&'static str, SerdeFns)> = self
+            .serde_fns
+            .lock()
+            .expect(SERDE_FN_POISON)
+            .iter()
+            .map(|(name, fns)| (*name, *fns))
+            .collect();
+
+        let mut components = BTreeMap::new();
+        for (name, fns) in serde_fns.iter() {
+            components.insert(name.to_string(), (fns.save)(self)?);
+        }
+
+        Ok(SerializedWorld {
+            entities,
+            components,
+        })
+    }
+
+    ///Replaces the state of this World with the snapshot. Every existing
+    ///Component, serializable or not, is dropped first, and Entity indices,
+    ///generations, and the recycling free-list come back exactly as saved.
+    ///
+    ///Returns Err, without modifying the World, if the snapshot names a
+    ///Component that wasn't registered via register_serializable_component(),
+    ///or if any Component fails to deserialize: every Component is
+    ///deserialized before anything in the World is touched.
+    pub fn load(&self, snapshot: &SerializedWorld) -> Result<(), ECSError> {
+        let decoded = self.decode_all(
+            snapshot
+                .components
+                .iter()
+                .map(|(name, saved)| (name, saved.as_slice())),
+        )?;
+
+        let old_entities = self.lock_entities().with_components();
+        self.clear_components(&old_entities);
+
+        self.replace_entities(snapshot.entities.clone());
+
+        for (fns, comps) in decoded {
+            (fns.commit)(self, &[], comps);
+        }
+
+        Ok(())
+    }
+
+    ///Deserializes the saved Components of every named type, pairing each
+    ///batch with its type's SerdeFns. The serde_fns mtx is released before
+    ///returning, so committing the batches can block on Storages freely.
+    fn decode_all<'a>(
+        &self,
+        named: impl Iterator<Item = (&'a String, &'a [(Entity, Value)])>,
+    ) -> Result<Vec<Decoded>, ECSError> {
+        let named: Vec<(SerdeFns, &[(Entity, Value)])> = {
+            let serde_fns = self.serde_fns.lock().expect(SERDE_FN_POISON);
+
+            named
+                .map(|(name, saved)| match serde_fns.get(name.as_str()) {
+                    Some(fns) => Ok((*fns, saved)),
+                    None => Err(ECSError::UnknownComponentName { name: name.clone() }),
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        named
+            .into_iter()
+            .map(|(fns, saved)| Ok((fns, (fns.decode)(saved)?)))
+            .collect()
+    }
+
+    ///Compares the current state of this World with previous, an earlier
+    ///save() of it, and returns only what has changed since: Components
+    ///added, changed, or removed, per serializable type, and the Entity list
+    ///if anything about it changed.
+    ///
+    ///A Component counts as changed when its serialized form differs; no
+    ///PartialEq impl is consulted. For most types that's the same thing, but
+    ///not for those whose serialized form depends on more than their value,
+    ///e.g. a HashSet or HashMap field, which may serialize its elements in a
+    ///different order each time. Those Components may be reported as changed
+    ///when they aren't, which costs bandwidth but is otherwise harmless.
+    ///
+    ///Pair this with save() to replicate a World: send a full save() once,
+    ///then a diff() against the last state sent, and WorldDelta::apply() each
+    ///one on the receiving World in order.
+    ///
+    ///As for save(), call this while no other thread is mutating the World.
+    pub fn diff(&self, previous: &SerializedWorld) -> Result<WorldDelta, ECSError> {
+        let current = self.save()?;
+
+        let entities_changed = serde_json::to_value(&current.entities).ok()
+            != serde_json::to_value(&previous.entities).ok();
+
+        let mut components = BTreeMap::new();
+        for (name, now) in current.components.iter() {
+            let before = previous.components.get(name).map_or(&[][..], |v| v.as_slice());
+            let delta = diff_storage(before, now);
+
+            if !delta.is_empty() {
+                components.insert(name.clone(), delta);
+            }
+        }
+
+        //A type that was since unregistered has lost every Component.
+        for (name, before) in previous.components.iter() {
+            if !current.components.contains_key(name) && !before.is_empty() {
+                let delta = diff_storage(before, &[]);
+                components.insert(name.clone(), delta);
+            }
+        }
+
+        Ok(WorldDelta {
+            entities: entities_changed.then_some(current.entities),
+            components,
+        })
+    }
+}
+
+impl WorldDelta {
+    ///True if nothing changed, so there's nothing worth sending.
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_none() && self.components.is_empty()
+    }
+
+    ///Brings world up to the state the delta was taken from, provided it was
+    ///in the state of the SerializedWorld the delta was diffed against; e.g.
+    ///by load()ing that, or applying every delta since in order.
+    ///
+    ///Entities of world that aren't alive in the new state are removed via
+    ///rm_entity() and then maintain_ecs(), so all of their Components go,
+    ///serializable or not, and on_remove() hooks fire for them. Serializable
+    ///Components are then updated directly, without firing hooks, just as
+    ///for World::load().
+    ///
+    ///Returns Err, without modifying world, if the delta names a Component
+    ///that wasn't registered via register_serializable_component(), or if
+    ///any Component fails to deserialize.
+    pub fn apply(&self, world: &World) -> Result<(), ECSError> {
+        let upserts: Vec<Vec<(Entity, Value)>> = self
+            .components
+            .values()
+            .map(|delta| delta.added.iter().chain(delta.changed.iter()).cloned().collect())
+            .collect();
+
+        let decoded = world.decode_all(
+            self.components
+                .keys()
+                .zip(upserts.iter())
+                .map(|(name, upserted)| (name, upserted.as_slice())),
+        )?;
+
+        if let Some(entities) = &self.entities {
+            let gone: Vec<Entity> = world
+                .lock_entities()
+                .living_iter()
+                .filter(|ent| !entities.is_alive(ent))
+                .copied()
+                .collect();
+
+            for ent in gone {
+                world.rm_entity(ent);
+            }
+            world.maintain_ecs();
+
+            world.replace_entities(entities.clone());
+        }
+
+        for ((fns, comps), delta) in decoded.into_iter().zip(self.components.values()) {
+            (fns.commit)(world, &delta.removed, comps);
+        }
+
+        Ok(())
+    }
+}
+
+///Both sides are as returned by save_storage(), one entry per Entity.
+fn diff_storage(before: &[(Entity, Value)], now: &[(Entity, Value)]) -> ComponentDelta {
+    let mut before: HashMap<Entity, &Value> = before.iter().map(|(e, v)| (*e, v)).collect();
+    let mut delta = ComponentDelta::default();
+
+    for (ent, value) in now {
+        match before.remove(ent) {
+            None => delta.added.push((*ent, value.clone())),
+            Some(old) if old != value => delta.changed.push((*ent, value.clone())),
+            Some(_) => {}
+        }
+    }
+
+    delta.removed = before.into_keys().collect();
+    delta.removed.sort_unstable();
+
+    delta
+}
+
+fn save_storage<T>(world: &World) -> Result<Vec<(Entity, Value)>, ECSError>
+where
+    T: Component + Serialize,
+{
+    let guard = world.req_read_guard::<T>();
+
+    let mut saved = guard
+        .raw()
+        .iter()
+        .map(|(ent, comp)| serde_json::to_value(comp).map(|value| (*ent, value)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ECSError::Serialize {
+            type_name: type_name::<T>(),
+        })?;
+
+    //HashMap order is arbitrary; sort so identical Worlds save identically.
+    saved.sort_unstable_by_key(|(ent, _)| *ent);
+
+    Ok(saved)
+}
+
+fn decode_storage<T>(saved: &[(Entity, Value)]) -> Result<Box<dyn Any>, ECSError>
+where
+    T: Component + DeserializeOwned,
+{
+    let decoded = saved
+        .iter()
+        .map(|(ent, value)| T::deserialize(value).map(|comp| (*ent, comp)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| ECSError::Deserialize {
+            type_name: type_name::<T>(),
+        })?;
+
+    Ok(Box::new(decoded))
+}
+
+fn commit_storage<T: Component>(world: &World, removed: &[Entity], decoded: Box<dyn Any>) {
+    let decoded = decoded
+        .downcast::<Vec<(Entity, T)>>()
+        .expect("decoded Components committed to the wrong type");
+
+    let mut guard = world.req_write_guard::<T>();
+    for ent in removed {
+        guard.remove(ent);
+    }
+    for (ent, comp) in *decoded {
+        guard.insert(ent, comp);
+    }
+}
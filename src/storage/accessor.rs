@@ -5,32 +5,686 @@
 //-------------- Tracks Access to Storages' Inner UnsafeCell ----------------
 //-----------------------------------------------------------------------------
 
-use std::sync::{Condvar, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+#[cfg(any(feature = "metrics", feature = "starvation-watchdog"))]
+use std::time::Instant;
+
+#[cfg(feature = "starvation-watchdog")]
+use super::watchdog;
+
+//Set in Accessor::fast_readers while the read fast path is closed; the
+//remaining bits count the readers that took the fast path.
+const FAST_GATE_CLOSED: u32 = 1 << 31;
 
 ///Abstraction Sequence:
 ///StorageGuard structs contain Accessor structs which contain AccessorState structs.
-
+///
 ///Used internally to guarantee safe concurrent access to Storages.
 #[derive(Debug)]
 pub struct Accessor {
     pub(crate) mtx: Mutex<AccessorState>,
     pub(crate) reader_cvar: Condvar,
     pub(crate) writer_cvar: Condvar,
+    pub(crate) priority: AccessPriority,
+    fast_readers: AtomicU32, //see init_read_access()
+    #[cfg(any(feature = "starvation-watchdog", feature = "trace"))]
+    type_name: &'static str, //of what this guards, for watchdog reports and traces
+}
+
+///How a reader was granted access, and so how it must give it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ReadAccess {
+    Fast,    //counted in Accessor::fast_readers only
+    Counted, //counted in AccessorState::readers
 }
 
 impl Accessor {
-    pub(super) fn new() -> Self {
+    ///type_name names what this guards, in starvation-watchdog reports and
+    ///access traces.
+    #[cfg_attr(
+        not(any(feature = "starvation-watchdog", feature = "trace")),
+        allow(unused_variables)
+    )]
+    pub(crate) fn new(priority: AccessPriority, type_name: &'static str) -> Self {
         Accessor {
             mtx: Mutex::new(AccessorState {
                 readers: 0,
                 read_allowed: true,
                 write_allowed: true,
                 writers_waiting: 0,
+                readers_waiting: 0,
+                upgradable_held: false,
+                upgrading: false,
+                next_ticket: 0,
+                now_serving: 0,
+                abandoned_tickets: Vec::new(),
+                #[cfg(feature = "metrics")]
+                stats: LockStats::default(),
             }),
             reader_cvar: Condvar::new(),
             writer_cvar: Condvar::new(),
+            priority,
+            fast_readers: AtomicU32::new(0),
+            #[cfg(any(feature = "starvation-watchdog", feature = "trace"))]
+            type_name,
+        }
+    }
+
+    ///Called internally whenever a ImmutStorageGuard is instantiated.
+    ///
+    ///While no writer holds, awaits, or is upgrading into this Storage, read
+    ///access is granted with a single atomic op, without touching the mtx;
+    ///see sync_fast_gate(). Otherwise the AccessorState decides, as ever.
+    pub(crate) fn init_read_access(&self) -> ReadAccess {
+        if self.try_fast_read() {
+            #[cfg(feature = "trace")]
+            self.trace("acquired read (fast path)", None);
+            return ReadAccess::Fast;
+        }
+
+        //While write access is NOT allowed, wait until the calling thread is
+        //notified on the condvar. Once the condvar is notified, the calling
+        //thread is awoken, the lock for the mutex is acquired, and execution
+        //of this function continues.
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.readers_waiting += 1;
+
+        #[cfg(feature = "trace")]
+        self.trace("waiting for read", Some(&accessor_state));
+
+        #[cfg(feature = "metrics")]
+        let slept = read_blocked(&accessor_state).then(Instant::now);
+
+        #[cfg(feature = "starvation-watchdog")]
+        {
+            accessor_state = self.watch_for_starvation(accessor_state);
+        }
+
+        accessor_state = self
+            .reader_cvar
+            .wait_while(accessor_state, |acc_state: &mut AccessorState| {
+                read_blocked(acc_state)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_read_wait(slept);
+
+        accessor_state.write_allowed = false;
+        accessor_state.readers += 1;
+        accessor_state.readers_waiting -= 1;
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired read", Some(&accessor_state));
+
+        ReadAccess::Counted
+    }
+
+    ///Waits for read access for up to the watchdog's threshold, reporting
+    ///this reader as starved if it still isn't allowed by then. The mtx is
+    ///released while reporting; readers are only ever woken by notify_all(),
+    ///so no wakeup meant for this reader can be lost meanwhile.
+    #[cfg(feature = "starvation-watchdog")]
+    fn watch_for_starvation<'a>(
+        &'a self,
+        accessor_state: MutexGuard<'a, AccessorState>,
+    ) -> MutexGuard<'a, AccessorState> {
+        let start = Instant::now();
+        let (accessor_state, _) = self
+            .reader_cvar
+            .wait_timeout_while(accessor_state, watchdog::threshold(), |acc_state| {
+                read_blocked(acc_state)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if !read_blocked(&accessor_state) {
+            return accessor_state;
+        }
+
+        drop(accessor_state);
+        watchdog::report(self.type_name, start.elapsed());
+        self.lock()
+    }
+
+    ///Called internally whenever a MutStorageGuard is instantiated.
+    pub(crate) fn init_write_access(&self) {
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.writers_waiting += 1;
+        self.sync_fast_gate(&accessor_state);
+        let ticket = self.take_ticket(&mut accessor_state);
+
+        #[cfg(feature = "trace")]
+        self.trace("waiting for write", Some(&accessor_state));
+
+        //While write access is NOT allowed, wait until the calling thread is
+        //notified on the condvar. Once the condvar is notified, the calling
+        //thread is awoken, the lock for the mutex is acquired, and execution
+        //of this function continues.
+        #[cfg(feature = "metrics")]
+        let slept = (self.write_blocked(&accessor_state) || !self.is_turn(&accessor_state, ticket))
+            .then(Instant::now);
+
+        accessor_state = self
+            .writer_cvar
+            .wait_while(accessor_state, |acc_state: &mut AccessorState| {
+                self.write_blocked(acc_state) || !self.is_turn(acc_state, ticket)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_write_wait(slept);
+
+        accessor_state.read_allowed = false;
+        accessor_state.write_allowed = false;
+        accessor_state.writers_waiting -= 1;
+        self.finish_ticket(&mut accessor_state, ticket);
+        self.sync_fast_gate(&accessor_state);
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired write", Some(&accessor_state));
+    }
+
+    ///Non-blocking counterpart to init_read_access(). Returns false, without
+    ///touching the AccessorState, if read access isn't allowed right now.
+    pub(crate) fn try_init_read_access(&self) -> bool {
+        let mut accessor_state = self.lock();
+
+        if read_blocked(&accessor_state) {
+            return false;
+        }
+
+        accessor_state.write_allowed = false;
+        accessor_state.readers += 1;
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired read (try)", Some(&accessor_state));
+
+        true
+    }
+
+    ///Non-blocking counterpart to init_write_access(). Returns false, without
+    ///touching the AccessorState, if any reader or writer holds this Storage.
+    pub(crate) fn try_init_write_access(&self) -> bool {
+        let mut accessor_state = self.lock();
+
+        //Close the fast path first, so no fast reader can slip in between
+        //counting them and taking write access.
+        self.fast_readers.fetch_or(FAST_GATE_CLOSED, Ordering::AcqRel);
+
+        //Under AccessPriority::FifoWriters, don't jump the queue.
+        let queued = accessor_state.next_ticket != accessor_state.now_serving;
+        if self.write_blocked(&accessor_state) || queued {
+            self.sync_fast_gate(&accessor_state);
+            return false;
+        }
+
+        accessor_state.read_allowed = false;
+        accessor_state.write_allowed = false;
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired write (try)", Some(&accessor_state));
+
+        true
+    }
+
+    ///Like init_read_access(), but gives up and returns false if read access
+    ///isn't granted within the given Duration.
+    pub(crate) fn init_read_access_timeout(&self, dur: Duration) -> bool {
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.readers_waiting += 1;
+
+        #[cfg(feature = "metrics")]
+        let slept = read_blocked(&accessor_state).then(Instant::now);
+
+        (accessor_state, _) = self
+            .reader_cvar
+            .wait_timeout_while(accessor_state, dur, |acc_state: &mut AccessorState| {
+                read_blocked(acc_state)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_read_wait(slept);
+
+        accessor_state.readers_waiting -= 1;
+
+        //Check the condition rather than the WaitTimeoutResult; access may
+        //have become available at the very moment the timeout elapsed.
+        if read_blocked(&accessor_state) {
+            #[cfg(feature = "trace")]
+            self.trace("timed out waiting for read", Some(&accessor_state));
+
+            //A reader-prioritized writer may be waiting on us to give up.
+            self.notify_next(&accessor_state);
+            return false;
+        }
+
+        accessor_state.write_allowed = false;
+        accessor_state.readers += 1;
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired read (timeout)", Some(&accessor_state));
+
+        true
+    }
+
+    ///Like init_write_access(), but gives up and returns false if write access
+    ///isn't granted within the given Duration.
+    pub(crate) fn init_write_access_timeout(&self, dur: Duration) -> bool {
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.writers_waiting += 1;
+        self.sync_fast_gate(&accessor_state);
+        let ticket = self.take_ticket(&mut accessor_state);
+
+        #[cfg(feature = "metrics")]
+        let slept = (self.write_blocked(&accessor_state) || !self.is_turn(&accessor_state, ticket))
+            .then(Instant::now);
+
+        (accessor_state, _) = self
+            .writer_cvar
+            .wait_timeout_while(accessor_state, dur, |acc_state: &mut AccessorState| {
+                self.write_blocked(acc_state) || !self.is_turn(acc_state, ticket)
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_write_wait(slept);
+
+        //Whether or not we got access, we are no longer waiting.
+        accessor_state.writers_waiting -= 1;
+
+        if self.write_blocked(&accessor_state) || !self.is_turn(&accessor_state, ticket) {
+            self.abandon_ticket(&mut accessor_state, ticket);
+            self.sync_fast_gate(&accessor_state);
+
+            #[cfg(feature = "trace")]
+            self.trace("timed out waiting for write", Some(&accessor_state));
+
+            //We may have consumed a notify_one() meant to hand off access, so
+            //pass it along to whoever is still waiting.
+            self.notify_next(&accessor_state);
+            return false;
+        }
+
+        accessor_state.read_allowed = false;
+        accessor_state.write_allowed = false;
+        self.finish_ticket(&mut accessor_state, ticket);
+        self.sync_fast_gate(&accessor_state);
+
+        #[cfg(feature = "trace")]
+        self.trace("acquired write (timeout)", Some(&accessor_state));
+
+        true
+    }
+
+    ///Writer-Prioritized Concurrent Access (the default, AccessPriority::Writers):
+    ///
+    ///These implementations should, assuming my logic is sound and correctly
+    ///implemented, eliminate the possibility of starvation for writers. Readers,
+    ///on the other hand, can VERY EASILY be starved if writers are continuously
+    ///requesting access. This is an intentional trade-off: the use case for this
+    ///ECS is turn-based video games, where reads occur every tick, but writes
+    ///occur only corresponding with user input.
+    ///
+    ///NOTE: This implementation does NOT guarantee that all readers will read the
+    ///result of every write. Many sequential writes may occur without any reads
+    ///in-between.
+    pub(crate) fn drop_read_access(&self, access: ReadAccess) {
+        if access == ReadAccess::Fast {
+            self.drop_fast_read();
+            #[cfg(feature = "trace")]
+            self.trace("released read (fast path)", None);
+            return;
+        }
+
+        let mut accessor_state = self.lock();
+
+        //This StorageGuard was granting non-exclusive Read access,
+        //so the reader count must be decremented.
+        accessor_state.readers -= 1;
+
+        if accessor_state.readers == 0 {
+            //There are no current readers, so write access is allowed.
+            accessor_state.write_allowed = true;
+
+            //Note: read_allowed is not and SHOULD NOT BE set to false
+            //here, because it is possible to reach 0 readers before
+            //the entire pool of notified readers have had a chance to
+            //read. By leaving read_allowed set to true, it gives these
+            //"late" readers a chance to race for the lock.
+            //
+            //Furthermore, and most importantly, setting read_allowed to
+            //false at this point introduces the possibility of an
+            //erronious reader lockout where there are no readers nor
+            //writers yet read_allowed is set to false. This would
+            //self-correct once a writer drops, but until that point
+            //behaviour would be incorrect.
+        }
+
+        #[cfg(feature = "trace")]
+        self.trace("released read", Some(&accessor_state));
+
+        self.notify_next(&accessor_state);
+    }
+
+    ///Called internally whenever an UpgradableStorageGuard is instantiated.
+    ///Upgradable access is read access, but only one thread may hold it.
+    pub(crate) fn init_upgradable_access(&self) {
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.readers_waiting += 1;
+
+        #[cfg(feature = "metrics")]
+        let slept = (read_blocked(&accessor_state) || accessor_state.upgradable_held)
+            .then(Instant::now);
+
+        accessor_state = self
+            .reader_cvar
+            .wait_while(accessor_state, |acc_state: &mut AccessorState| {
+                read_blocked(acc_state) || acc_state.upgradable_held
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_read_wait(slept);
+
+        accessor_state.write_allowed = false;
+        accessor_state.readers += 1;
+        accessor_state.readers_waiting -= 1;
+        accessor_state.upgradable_held = true;
+    }
+
+    ///Turns the calling thread's upgradable access into write access. New
+    ///readers are turned away and plain writers are held off (see
+    ///write_blocked()) while the remaining readers drain, so no other writer
+    ///can slip in between the read and the write.
+    pub(crate) fn upgrade_access(&self) {
+        let mut accessor_state: MutexGuard<'_, AccessorState> = self.lock();
+
+        accessor_state.readers -= 1;
+        accessor_state.read_allowed = false;
+        accessor_state.upgrading = true;
+        self.sync_fast_gate(&accessor_state);
+
+        #[cfg(feature = "metrics")]
+        let slept = (accessor_state.readers > 0 || self.fast_read_count() > 0).then(Instant::now);
+
+        accessor_state = self
+            .writer_cvar
+            .wait_while(accessor_state, |acc_state: &mut AccessorState| {
+                acc_state.readers > 0 || self.fast_read_count() > 0
+            })
+            .unwrap_or_else(PoisonError::into_inner);
+
+        #[cfg(feature = "metrics")]
+        accessor_state.stats.record_write_wait(slept);
+
+        accessor_state.write_allowed = false;
+        accessor_state.upgrading = false;
+        accessor_state.upgradable_held = false;
+    }
+
+    pub(crate) fn drop_upgradable_access(&self) {
+        let mut accessor_state = self.lock();
+
+        accessor_state.readers -= 1;
+        accessor_state.upgradable_held = false;
+
+        if accessor_state.readers == 0 {
+            accessor_state.write_allowed = true;
         }
+
+        self.notify_next(&accessor_state);
+
+        //Wake anyone waiting specifically for the upgradable slot, which
+        //notify_next() may have skipped in favor of a writer.
+        self.reader_cvar.notify_all();
+    }
+
+    pub(crate) fn drop_write_access(&self) {
+        let mut accessor_state = self.lock();
+
+        //This StorageGuard was giving exclusive Write access, so it is
+        //now safe to allow any type of access.
+        accessor_state.write_allowed = true;
+        accessor_state.read_allowed = true;
+        self.sync_fast_gate(&accessor_state);
+
+        #[cfg(feature = "trace")]
+        self.trace("released write", Some(&accessor_state));
+
+        self.notify_next(&accessor_state);
     }
+
+    ///Logs an access event at trace level, under the target
+    ///"ecs_it::access", with the calling thread and the counts it left the
+    ///AccessorState in. acc_state is None on the read fast path, which
+    ///never locks the mtx; only the fast reader count is logged then.
+    #[cfg(feature = "trace")]
+    fn trace(&self, event: &str, acc_state: Option<&AccessorState>) {
+        let thread = std::thread::current();
+        let fast_readers = self.fast_read_count();
+
+        match acc_state {
+            Some(acc_state) => log::trace!(
+                target: "ecs_it::access",
+                "{:?} ({}) {} {}: readers={} fast_readers={} readers_waiting={} \
+                 writers_waiting={} read_allowed={} write_allowed={}",
+                thread.id(),
+                thread.name().unwrap_or("unnamed"),
+                event,
+                self.type_name,
+                acc_state.readers,
+                fast_readers,
+                acc_state.readers_waiting,
+                acc_state.writers_waiting,
+                acc_state.read_allowed,
+                acc_state.write_allowed,
+            ),
+            None => log::trace!(
+                target: "ecs_it::access",
+                "{:?} ({}) {} {}: fast_readers={}",
+                thread.id(),
+                thread.name().unwrap_or("unnamed"),
+                event,
+                self.type_name,
+                fast_readers,
+            ),
+        }
+    }
+
+    ///Locks the AccessorState, recovering it if a thread panicked while
+    ///holding the mtx. It holds only counters and flags, each of which is
+    ///left consistent between statements, so the Storage stays usable
+    ///rather than every later request panicking too.
+    fn lock(&self) -> MutexGuard<'_, AccessorState> {
+        self.mtx.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    ///(readers_waiting, writers_waiting, upgrading), so that tests can wait
+    ///until a request is actually queued rather than sleep and hope.
+    #[cfg(test)]
+    pub(crate) fn waiting(&self) -> (u16, u16, bool) {
+        let acc_state = self.lock();
+        (acc_state.readers_waiting, acc_state.writers_waiting, acc_state.upgrading)
+    }
+
+    ///Snapshot of how often, and for how long, threads have slept waiting for
+    ///access to this Storage.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn lock_stats(&self) -> LockStats {
+        self.lock().stats
+    }
+
+    ///Under AccessPriority::Readers a writer must also wait until no readers
+    ///are waiting, so readers are never starved (but writers can be).
+    fn write_blocked(&self, acc_state: &AccessorState) -> bool {
+        if acc_state.upgrading || self.fast_read_count() > 0 {
+            return true;
+        }
+
+        match self.priority {
+            AccessPriority::Writers | AccessPriority::FifoWriters => !acc_state.write_allowed,
+            AccessPriority::Readers => !acc_state.write_allowed || acc_state.readers_waiting > 0,
+        }
+    }
+
+    //The read fast path: fast_readers is a count of readers that skipped the
+    //mtx, plus the FAST_GATE_CLOSED bit. Readers only take the fast path by
+    //incrementing the count while the bit is clear, and writers only count
+    //fast readers after setting the bit, all on the one atomic, so a writer
+    //that sees a count of 0 knows no fast reader can arrive until it reopens.
+
+    fn try_fast_read(&self) -> bool {
+        let mut current = self.fast_readers.load(Ordering::Relaxed);
+
+        loop {
+            if current & FAST_GATE_CLOSED != 0 || current == FAST_GATE_CLOSED - 1 {
+                return false;
+            }
+
+            match self.fast_readers.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn drop_fast_read(&self) {
+        let prev = self.fast_readers.fetch_sub(1, Ordering::AcqRel);
+
+        //The last fast reader out while the gate is closed may be all that a
+        //writer is waiting on. Locking the mtx first means that writer is
+        //either already asleep on the condvar, or yet to check the count.
+        if prev == FAST_GATE_CLOSED | 1 {
+            let accessor_state = self.lock();
+            self.notify_next(&accessor_state);
+        }
+    }
+
+    fn fast_read_count(&self) -> u32 {
+        self.fast_readers.load(Ordering::Acquire) & !FAST_GATE_CLOSED
+    }
+
+    ///Opens the fast path only while plain readers are all that is going on.
+    ///Must be called, with the mtx held, after any change to the fields read
+    ///here.
+    fn sync_fast_gate(&self, acc_state: &AccessorState) {
+        if acc_state.read_allowed && acc_state.writers_waiting == 0 && !acc_state.upgrading {
+            self.fast_readers.fetch_and(!FAST_GATE_CLOSED, Ordering::AcqRel);
+        } else {
+            self.fast_readers.fetch_or(FAST_GATE_CLOSED, Ordering::AcqRel);
+        }
+    }
+
+    //Writer tickets are only issued under AccessPriority::FifoWriters; under
+    //any other priority every writer is always considered to be next in line.
+
+    fn take_ticket(&self, acc_state: &mut AccessorState) -> Option<u64> {
+        if self.priority != AccessPriority::FifoWriters {
+            return None;
+        }
+
+        let ticket = acc_state.next_ticket;
+        acc_state.next_ticket += 1;
+        Some(ticket)
+    }
+
+    fn is_turn(&self, acc_state: &AccessorState, ticket: Option<u64>) -> bool {
+        ticket.is_none_or(|ticket| ticket == acc_state.now_serving)
+    }
+
+    ///Called once the ticket's holder has write access.
+    fn finish_ticket(&self, acc_state: &mut AccessorState, ticket: Option<u64>) {
+        if ticket.is_some() {
+            acc_state.advance_serving();
+        }
+    }
+
+    ///Called when the ticket's holder gives up waiting, so that the writers
+    ///queued behind it aren't stuck waiting for a turn that never comes.
+    fn abandon_ticket(&self, acc_state: &mut AccessorState, ticket: Option<u64>) {
+        match ticket {
+            Some(ticket) if ticket == acc_state.now_serving => acc_state.advance_serving(),
+            Some(ticket) => acc_state.abandoned_tickets.push(ticket),
+            None => {}
+        }
+    }
+
+    ///Wakes whichever waiting thread(s) should be serviced next, according to
+    ///this Storage's AccessPriority.
+    fn notify_next(&self, acc_state: &AccessorState) {
+        if acc_state.upgrading {
+            //Only the upgrading thread may proceed, and notify_one() could
+            //wake some other writer instead, so wake them all.
+            self.writer_cvar.notify_all();
+            return;
+        }
+
+        match self.priority {
+            AccessPriority::Writers => {
+                if acc_state.writers_waiting > 0 {
+                    self.writer_cvar.notify_one();
+                } else {
+                    self.reader_cvar.notify_all();
+                }
+            }
+            AccessPriority::FifoWriters => {
+                if acc_state.writers_waiting > 0 {
+                    //notify_one() might wake a writer whose turn it isn't.
+                    self.writer_cvar.notify_all();
+                } else {
+                    self.reader_cvar.notify_all();
+                }
+            }
+            AccessPriority::Readers => {
+                if acc_state.readers_waiting > 0 {
+                    self.reader_cvar.notify_all();
+                } else {
+                    self.writer_cvar.notify_one();
+                }
+            }
+        }
+    }
+}
+
+///True if a new reader must wait. readers is a u16, so once u16::MAX readers
+///hold a Storage, further readers wait for one to drop rather than overflow.
+fn read_blocked(acc_state: &AccessorState) -> bool {
+    !acc_state.read_allowed || acc_state.readers == u16::MAX
+}
+
+///Which kind of waiting thread a Storage services first whenever access to it
+///is released. Chosen per Storage at registration time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AccessPriority {
+    ///Readers never starve; writers may wait indefinitely under constant reads.
+    Readers,
+    ///Writers never starve; readers may wait indefinitely under constant writes.
+    #[default]
+    Writers,
+    ///Like Writers, but waiting writers are granted access strictly in the
+    ///order they requested it, rather than in whatever order the OS happens
+    ///to wake them. Every release wakes all waiting writers so that the one
+    ///whose turn it is can proceed, which costs throughput when many writers
+    ///queue at once; choose it when predictable latency matters more.
+    FifoWriters,
 }
 
 ///Internal to Accessor structs.
@@ -40,4 +694,59 @@ pub struct AccessorState {
     pub read_allowed: bool,
     pub write_allowed: bool,
     pub writers_waiting: u16, //slept writers, NOT current writers (which is always 0..1)
+    pub readers_waiting: u16, //slept readers, NOT current readers
+    pub upgradable_held: bool, //an UpgradableStorageGuard exists (counted in readers)
+    pub upgrading: bool,      //its holder is waiting for the other readers to drain
+    pub next_ticket: u64,     //FifoWriters: handed to the next writer to wait
+    pub now_serving: u64,     //FifoWriters: the ticket allowed to write next
+    pub abandoned_tickets: Vec<u64>, //FifoWriters: held by writers that timed out
+    #[cfg(feature = "metrics")]
+    pub stats: LockStats,
+}
+
+impl AccessorState {
+    ///Moves on to the next ticket whose holder is still waiting.
+    fn advance_serving(&mut self) {
+        self.now_serving += 1;
+
+        while let Some(pos) = self
+            .abandoned_tickets
+            .iter()
+            .position(|ticket| *ticket == self.now_serving)
+        {
+            self.abandoned_tickets.swap_remove(pos);
+            self.now_serving += 1;
+        }
+    }
+}
+
+///Lock contention counters for one Storage, from World::lock_stats(). Only
+///requests that actually had to sleep are counted; uncontended requests, and
+///the non-blocking try_ requests, are not. Wait times include time spent
+///waiting on requests that then timed out.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LockStats {
+    pub total_read_waits: u64,
+    pub total_write_waits: u64,
+    pub read_wait_time: Duration,
+    pub write_wait_time: Duration,
+}
+
+#[cfg(feature = "metrics")]
+impl LockStats {
+    fn record_read_wait(&mut self, slept: Option<Instant>) {
+        if let Some(start) = slept {
+            self.total_read_waits += 1;
+            self.read_wait_time += start.elapsed();
+        }
+    }
+
+    //Upgrading counts as a write wait.
+    fn record_write_wait(&mut self, slept: Option<Instant>) {
+        if let Some(start) = slept {
+            self.total_write_waits += 1;
+            self.write_wait_time += start.elapsed();
+        }
+    }
 }
@@ -0,0 +1,117 @@
+//-----------------------------------------------------------------------------
+//------------- Debug-Only Tracking of Storages Held Per Thread ---------------
+//-----------------------------------------------------------------------------
+
+//Catches the two classic ways to deadlock on Storages: asking again for a
+//Storage the calling thread already holds in a conflicting way, and taking
+//Storages in something other than ascending TypeId order (the order Joins
+//use), which deadlocks as soon as another thread takes them the other way.
+//The order check only applies when the held or requested access is more
+//than plain read access: TypeId order can't be predicted, so flagging two
+//readers that happen to nest would make an innocent expression such as
+//(w.req_read_guard::<A>().len(), w.req_read_guard::<B>().len()) panic or not
+//depending on the build.
+//
+//Only blocking requests are checked, since try_* and *_timeout requests give
+//up rather than deadlock, and upgrade() isn't checked at all. Tracking is per
+//thread, so a guard dropped on a different thread than the one that acquired
+//it is not untracked. FrameAccess opts out of the order check, since it
+//documents its own (declaration) order. Compiles down to nothing without
+//debug_assertions.
+//
+//Storages are identified by a HeldId, which also orders the back buffer of
+//a double-buffered Component type right after its front buffer.
+
+///(TypeId of the Component type, whether this is its back buffer)
+pub(super) type HeldId = (std::any::TypeId, bool);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Held {
+    Read,
+    Upgradable,
+    Write,
+}
+
+#[cfg(debug_assertions)]
+mod tracking {
+    use super::{Held, HeldId};
+    use std::cell::{Cell, RefCell};
+
+    thread_local! {
+        static HELD: RefCell<Vec<(HeldId, &'static str, Held)>> = const { RefCell::new(Vec::new()) };
+        static ORDER_EXEMPT: Cell<bool> = const { Cell::new(false) };
+    }
+
+    pub(crate) fn without_order_check<R>(f: impl FnOnce() -> R) -> R {
+        let was_exempt = ORDER_EXEMPT.replace(true);
+        let ret = f();
+        ORDER_EXEMPT.set(was_exempt);
+        ret
+    }
+
+    pub(in super::super) fn check(type_id: HeldId, type_name: &'static str, kind: Held) {
+        HELD.with_borrow(|held| {
+            for (held_id, held_name, held_kind) in held.iter() {
+                if *held_id == type_id && conflicts(*held_kind, kind) {
+                    panic!(
+                        "deadlock: this thread requested {:?} access to {} while already \
+                         holding {:?} access to it",
+                        kind, type_name, held_kind
+                    );
+                }
+
+                let any_write = *held_kind != Held::Read || kind != Held::Read;
+                if *held_id > type_id && any_write && !ORDER_EXEMPT.get() {
+                    panic!(
+                        "possible deadlock: this thread requested {} while holding {}; \
+                         acquire Storages in ascending TypeId order, as join! does",
+                        type_name, held_name
+                    );
+                }
+            }
+        });
+    }
+
+    pub(in super::super) fn acquired(type_id: HeldId, type_name: &'static str, kind: Held) {
+        HELD.with_borrow_mut(|held| held.push((type_id, type_name, kind)));
+    }
+
+    pub(in super::super) fn released(type_id: HeldId, kind: Held) {
+        HELD.with_borrow_mut(|held| {
+            if let Some(pos) = held
+                .iter()
+                .rposition(|(held_id, _, held_kind)| *held_id == type_id && *held_kind == kind)
+            {
+                held.remove(pos);
+            }
+        });
+    }
+
+    ///Readers may share a Storage; anything involving a writer may not. A
+    ///thread holding read access which asks for more read access is fine.
+    fn conflicts(held: Held, requested: Held) -> bool {
+        !matches!(
+            (held, requested),
+            (Held::Read, Held::Read) | (Held::Upgradable, Held::Read) | (Held::Read, Held::Upgradable)
+        )
+    }
+}
+
+#[cfg(debug_assertions)]
+pub(super) use tracking::{acquired, check, released};
+#[cfg(debug_assertions)]
+pub(crate) use tracking::without_order_check;
+
+#[cfg(not(debug_assertions))]
+pub(crate) fn without_order_check<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+#[cfg(not(debug_assertions))]
+pub(super) fn check(_: HeldId, _: &'static str, _: Held) {}
+
+#[cfg(not(debug_assertions))]
+pub(super) fn acquired(_: HeldId, _: &'static str, _: Held) {}
+
+#[cfg(not(debug_assertions))]
+pub(super) fn released(_: HeldId, _: Held) {}
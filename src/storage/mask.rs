@@ -0,0 +1,238 @@
+//-----------------------------------------------------------------------------
+//------------------- Per-Entity Component Presence Bitsets -------------------
+//-----------------------------------------------------------------------------
+
+use std::{
+    ops::{BitOr, BitOrAssign},
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use super::super::Entity;
+
+const MASKS_POISON: &str = "entity masks RwLock found poisoned in mask.rs";
+
+///How many Component types can be given a bit; see World::component_mask().
+pub(crate) const MAX_MASK_BITS: u32 = 128;
+
+///A set of Component types, one bit per type, as built from
+///World::component_mask() and compared against World::entity_mask().
+///
+///# Example
+///```
+/// use ecs_it::*;
+///
+/// struct Pos(i32);
+/// struct Vel(i32);
+/// impl Component for Pos {}
+/// impl Component for Vel {}
+///
+/// let world = world::World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Vel>();
+///
+/// let ent = world.create_entity();
+/// world.add_component(ent, Pos(0));
+///
+/// let moving = world.component_mask::<Pos>() | world.component_mask::<Vel>();
+/// assert!(!world.matches(ent, &moving));
+///
+/// world.add_component(ent, Vel(1));
+/// assert!(world.matches(ent, &moving));
+///```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ComponentMask(u128);
+
+impl ComponentMask {
+    pub fn empty() -> Self {
+        ComponentMask(0)
+    }
+
+    pub(crate) fn from_bit(bit: u32) -> Self {
+        ComponentMask(1 << bit)
+    }
+
+    ///True if every Component type in other is also in self.
+    pub fn contains(&self, other: &ComponentMask) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for ComponentMask {
+    type Output = ComponentMask;
+
+    fn bitor(self, rhs: ComponentMask) -> ComponentMask {
+        ComponentMask(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for ComponentMask {
+    fn bitor_assign(&mut self, rhs: ComponentMask) {
+        self.0 |= rhs.0;
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+///The ComponentMask of every Entity, indexed by Entity index and shared by
+///World and every Storage. Each Storage only ever touches its own bit, and
+///only while write-locked, so the words are atomics behind a RwLock that is
+///write-locked only to grow.
+///
+///Each index also records the generation its bits belong to, as claimed by
+///World on creating an Entity there. Bits are only read or written through a
+///handle of that generation, so a stale handle can neither see nor disturb
+///the mask of whichever Entity now has its index.
+#[derive(Debug, Default)]
+pub(crate) struct EntityMasks {
+    slots: RwLock<Vec<MaskSlot>>,
+}
+
+#[derive(Debug, Default)]
+struct MaskSlot {
+    generation: AtomicU32,
+    words: [AtomicU64; 2],
+}
+
+impl MaskSlot {
+    fn is_of(&self, e: &Entity) -> bool {
+        self.generation.load(Ordering::SeqCst) == e.generation()
+    }
+}
+
+impl EntityMasks {
+    pub(crate) fn get(&self, e: &Entity) -> ComponentMask {
+        let slots = self.slots.read().expect(MASKS_POISON);
+
+        match slots.get(e.index() as usize) {
+            Some(slot) if slot.is_of(e) => {
+                let lo = slot.words[0].load(Ordering::Acquire) as u128;
+                let hi = slot.words[1].load(Ordering::Acquire) as u128;
+                ComponentMask(hi << 64 | lo)
+            }
+            _ => ComponentMask::empty(),
+        }
+    }
+
+    ///Hands the index over to e's generation, with no bits set, unless it is
+    ///already e's. Called whenever World creates or swaps in an Entity.
+    pub(crate) fn claim(&self, e: &Entity) {
+        self.with_slot(e.index(), |slot| {
+            if slot.is_of(e) {
+                return;
+            }
+
+
+            //Generation first: a stale set() that slips in before the words
+            //are zeroed is wiped by them, and one after sees the new
+            //generation and backs out.
+            slot.generation.store(e.generation(), Ordering::SeqCst);
+            slot.words[0].store(0, Ordering::SeqCst);
+            slot.words[1].store(0, Ordering::SeqCst);
+        });
+    }
+
+    fn set(&self, e: &Entity, bit: u32) {
+        let (word, flag) = (bit as usize / 64, 1 << (bit % 64));
+
+        self.with_slot(e.index(), |slot| {
+            if !slot.is_of(e) {
+                return;
+            }
+
+            slot.words[word].fetch_or(flag, Ordering::SeqCst);
+
+            //Claimed for a newer generation meanwhile. Only the caller, which
+            //holds the write guard for this bit, can have set it since.
+            if !slot.is_of(e) {
+                slot.words[word].fetch_and(!flag, Ordering::SeqCst);
+            }
+        });
+    }
+
+    fn unset(&self, e: &Entity, bit: u32) {
+        let slots = self.slots.read().expect(MASKS_POISON);
+
+        if let Some(slot) = slots.get(e.index() as usize) {
+            if slot.is_of(e) {
+                slot.words[bit as usize / 64].fetch_and(!(1 << (bit % 64)), Ordering::SeqCst);
+            }
+        }
+    }
+
+    ///Unsets the bit for every Entity, e.g. once its Storage is gone.
+    pub(crate) fn clear_bit(&self, bit: u32) {
+        let slots = self.slots.read().expect(MASKS_POISON);
+
+        for slot in slots.iter() {
+            slot.words[bit as usize / 64].fetch_and(!(1 << (bit % 64)), Ordering::SeqCst);
+        }
+    }
+
+    ///Runs f on the slot for index, growing the list to hold it if needed.
+    fn with_slot(&self, index: u32, f: impl FnOnce(&MaskSlot)) {
+        let index = index as usize;
+
+        {
+            let slots = self.slots.read().expect(MASKS_POISON);
+            if let Some(slot) = slots.get(index) {
+                return f(slot);
+            }
+        }
+
+        let mut slots = self.slots.write().expect(MASKS_POISON);
+        if slots.len() <= index {
+            slots.resize_with(index + 1, Default::default);
+        }
+        f(&slots[index]);
+    }
+}
+
+///A Storage's handle on its own bit of the EntityMasks. Component types
+///registered after the first MAX_MASK_BITS have no bit, and every method is
+///then a no-op.
+#[derive(Debug)]
+pub(crate) struct MaskBit {
+    masks: Arc<EntityMasks>,
+    bit: Option<u32>,
+}
+
+impl MaskBit {
+    pub(crate) fn new(masks: Arc<EntityMasks>, bit: Option<u32>) -> Self {
+        MaskBit { masks, bit }
+    }
+
+    pub(crate) fn bit(&self) -> Option<u32> {
+        self.bit
+    }
+
+    pub(super) fn set(&self, e: &Entity) {
+        if let Some(bit) = self.bit {
+            self.masks.set(e, bit);
+        }
+    }
+
+    pub(super) fn unset(&self, e: &Entity) {
+        if let Some(bit) = self.bit {
+            self.masks.unset(e, bit);
+        }
+    }
+
+    ///Rebuilds the bit from scratch, for after changes that couldn't be
+    ///tracked one Entity at a time.
+    pub(super) fn resync<'a>(&self, present: impl Iterator<Item = &'a Entity>) {
+        if let Some(bit) = self.bit {
+            self.masks.clear_bit(bit);
+
+            for e in present {
+                self.masks.set(e, bit);
+            }
+        }
+    }
+}
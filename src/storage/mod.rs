@@ -5,15 +5,39 @@
 //-------------------------- ECS Component Storages ---------------------------
 //-----------------------------------------------------------------------------
 
-use std::{any::Any, cell::UnsafeCell, collections::HashMap, sync::Arc};
-
-use super::{Component, Entity};
+use std::{
+    any::{type_name, Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::{world::World, Component, Entity};
 
 mod accessor;
+mod held;
+mod mask;
 mod storage_guard;
-
-use accessor::{Accessor, AccessorState};
-pub use storage_guard::{ImmutableStorageGuard, MutableStorageGuard};
+mod token;
+#[cfg(feature = "starvation-watchdog")]
+mod watchdog;
+
+pub use accessor::AccessPriority;
+#[cfg(feature = "metrics")]
+pub use accessor::LockStats;
+pub(crate) use accessor::{Accessor, ReadAccess};
+use held::{Held, HeldId};
+pub(crate) use held::without_order_check;
+pub use mask::ComponentMask;
+pub(crate) use mask::{EntityMasks, MaskBit, MAX_MASK_BITS};
+pub use storage_guard::{ImmutableStorageGuard, MutableStorageGuard, UpgradableStorageGuard};
+pub use token::StorageToken;
+#[cfg(feature = "starvation-watchdog")]
+pub use watchdog::set_starvation_watchdog;
 
 ///Used internally to provide abstraction over generically typed Storages
 ///to allow storing of any kind of Storage<T> inside of World without having
@@ -21,6 +45,10 @@ pub use storage_guard::{ImmutableStorageGuard, MutableStorageGuard};
 //#[derive(Debug)]
 pub(crate) struct StorageBox {
     pub(crate) boxed: Arc<dyn Any + Send + Sync + 'static>,
+    pub(crate) shrink: fn(&World), //World::shrink_storages() for this Storage
+    pub(crate) clear: fn(&World),  //World::reset() for this Storage
+    //World::component_types(), which only asks front buffers; None for back buffers
+    pub(crate) contains: Option<fn(&World, Entity) -> bool>,
 }
 
 impl StorageBox {
@@ -32,80 +60,83 @@ impl StorageBox {
     }
 }
 
-pub(crate) trait AnyStorage {
-    fn rm_component(&self, e: &Entity);
-}
-
 //-----------------------------------------------------------------------------
 
 ///Used internally to store components of a single type, and to control both
 ///mutable and immutable access to said storage.
+///
+///Storages are keyed by Entity rather than indexed by it, so they are already
+///sparse: memory and iteration cost scale with the number of Components of
+///this type, not with the number of Entities in the World. A tag Component on
+///1 of 10,000 Entities costs one map entry. For that reason there is no
+///separate sparse backend; the trade-off versus a dense Vec<Option<T>> is a
+///hash per get()/insert() in exchange for never paying for empty slots.
 #[derive(Debug)]
 pub(crate) struct Storage<T> {
-    accessor: Accessor,
+    pub(super) accessor: Accessor,
     inner: UnsafeCell<HashMap<Entity, T>>,
+    changed: UnsafeCell<HashMap<Entity, u64>>, //tick each Component last changed
+    world_tick: Arc<AtomicU64>,                //shared with World
+    mask: MaskBit,                             //this type's bit in World's EntityMasks
+    back_buffer: bool,                         //see World::register_double_buffered()
 }
 
+///Stands in for T when keying the maintenance fn of the back buffer of a
+///double-buffered T, so that it doesn't replace that of the front buffer.
+pub(crate) struct BackBuffer<T>(PhantomData<T>);
+
 unsafe impl<T> Sync for Storage<T> where T: Component {}
 
 impl<T> Storage<T>
 where
     T: Component,
 {
-    pub(crate) fn new() -> Self {
-        let new_map = HashMap::new();
+    pub(crate) fn new(
+        priority: AccessPriority,
+        world_tick: Arc<AtomicU64>,
+        capacity: usize,
+        mask: MaskBit,
+    ) -> Self {
+        let new_map = HashMap::with_capacity(capacity);
 
         Storage {
-            accessor: Accessor::new(),
+            accessor: Accessor::new(priority, type_name::<T>()),
             inner: UnsafeCell::new(new_map),
+            changed: UnsafeCell::new(HashMap::new()),
+            world_tick,
+            mask,
+            back_buffer: false,
         }
     }
 
-    ///Called internally whenever a ImmutStorageGuard is instantiated.
-    pub(super) fn init_read_access(&self) {
-        const READ_ERR_MSG: &str = "Accessor mtx found poisoned";
-
-        //While write access is NOT allowed, wait until the calling thread is
-        //notified on the condvar. Once the condvar is notified, the calling
-        //thread is awoken, the lock for the mutex is acquired, and execution
-        //of this function continues.
-        let mut accessor_state: std::sync::MutexGuard<'_, AccessorState> = self
-            .accessor
-            .reader_cvar
-            .wait_while(
-                self.accessor.mtx.lock().expect(READ_ERR_MSG),
-                |acc_state: &mut AccessorState| !acc_state.read_allowed,
-            )
-            .expect(READ_ERR_MSG);
-
-        accessor_state.write_allowed = false;
-        accessor_state.readers += 1;
+    pub(crate) fn into_back_buffer(mut self) -> Self {
+        self.back_buffer = true;
+        self
     }
 
-    ///Called internally whenever a MutStorageGuard is instantiated.
-    pub(super) fn init_write_access(&self) {
-        const WRITE_ERR_MSG: &str = "Accessor mtx found poisoned in StorageGuard.val_mut().";
-
-        let mut accessor_state: std::sync::MutexGuard<'_, AccessorState> =
-            self.accessor.mtx.lock().expect(WRITE_ERR_MSG);
-
-        accessor_state.writers_waiting += 1;
-
-        //While write access is NOT allowed, wait until the calling thread is
-        //notified on the condvar. Once the condvar is notified, the calling
-        //thread is awoken, the lock for the mutex is acquired, and execution
-        //of this function continues.
-        accessor_state = self
-            .accessor
-            .writer_cvar
-            .wait_while(accessor_state, |acc_state: &mut AccessorState| {
-                !acc_state.write_allowed
-            })
-            .expect(WRITE_ERR_MSG);
-
-        accessor_state.read_allowed = false;
-        accessor_state.write_allowed = false;
-        accessor_state.writers_waiting -= 1;
+    ///What this Storage is tracked as by the debug-only held-guard checks.
+    pub(super) fn held_id(&self) -> HeldId {
+        (TypeId::of::<T>(), self.back_buffer)
+    }
+
+    ///Swaps the contents of this, the front buffer of a double-buffered T,
+    ///with those of its back buffer, write-locking the front first.
+    pub(crate) fn swap_buffers(&self, back: &Storage<T>) {
+        held::check(self.held_id(), type_name::<T>(), Held::Write);
+        held::check(back.held_id(), type_name::<T>(), Held::Write);
+
+        self.accessor.init_write_access();
+        back.accessor.init_write_access();
+
+        std::mem::swap(self.unsafe_borrow_mut(), back.unsafe_borrow_mut());
+        std::mem::swap(
+            self.unsafe_borrow_changed_mut(),
+            back.unsafe_borrow_changed_mut(),
+        );
+        self.mask.resync(self.unsafe_borrow().keys());
+
+        back.accessor.drop_write_access();
+        self.accessor.drop_write_access();
     }
 
     ///Called internally only by ImmutableStorageGuard API.
@@ -113,77 +144,45 @@ where
         unsafe { &*self.inner.get() }
     }
 
-    ///Called internally only by MutableStorageGuard API.
+    ///Called internally only by MutableStorageGuard API. Exclusivity of the
+    ///returned borrow is guaranteed by the Accessor, not by the borrow checker.
+    #[allow(clippy::mut_from_ref)]
     pub(super) fn unsafe_borrow_mut(&self) -> &mut HashMap<Entity, T> {
         unsafe { &mut *self.inner.get() }
     }
 
-    ///Writer-Prioritized Concurrent Access:
-    ///
-    ///These implementations should, assuming my logic is sound and correctly
-    ///implemented, eliminate the possibility of starvation for writers. Readers,
-    ///on the other hand, can VERY EASILY be starved if writers are continuously
-    ///requesting access. This is an intentional trade-off: the use case for this
-    ///ECS is turn-based video games, where reads occur every tick, but writes
-    ///occur only corresponding with user input.
-    ///
-    ///NOTE: This implementation does NOT guarantee that all readers will read the
-    ///result of every write. Many sequential writes may occur without any reads
-    ///in-between.
-    pub(super) fn drop_read_access(&self) {
-        let mut accessor_state = self
-            .accessor
-            .mtx
-            .lock()
-            .expect("StorageGuard Mutex poisoned before .drop()");
-
-        //This StorageGuard was granting non-exclusive Read access,
-        //so the reader count must be decremented.
-        accessor_state.readers -= 1;
-
-        if accessor_state.readers == 0 {
-            //There are no current readers, so write access is allowed.
-            accessor_state.write_allowed = true;
-
-            //Note: read_allowed is not and SHOULD NOT BE set to false
-            //here, because it is possible to reach 0 readers before
-            //the entire pool of notified readers have had a chance to
-            //read. By leaving read_allowed set to true, it gives these
-            //"late" readers a chance to race for the lock.
-            //
-            //Furthermore, and most importantly, setting read_allowed to
-            //false at this point introduces the possibility of an
-            //erronious reader lockout where there are no readers nor
-            //writers yet read_allowed is set to false. This would
-            //self-correct once a writer drops, but until that point
-            //behaviour would be incorrect.
-        }
+    ///Called internally only by StorageGuard APIs; same rules as unsafe_borrow().
+    pub(super) fn unsafe_borrow_changed(&self) -> &HashMap<Entity, u64> {
+        unsafe { &*self.changed.get() }
+    }
 
-        //Writer prioritization:
-        if accessor_state.writers_waiting > 0 {
-            self.accessor.writer_cvar.notify_one();
-        } else {
-            self.accessor.reader_cvar.notify_all();
-        }
+    ///Called internally only by MutableStorageGuard API; same rules as
+    ///unsafe_borrow_mut().
+    #[allow(clippy::mut_from_ref)]
+    pub(super) fn unsafe_borrow_changed_mut(&self) -> &mut HashMap<Entity, u64> {
+        unsafe { &mut *self.changed.get() }
     }
 
-    pub(super) fn drop_write_access(&self) {
-        let mut accessor_state = self
-            .accessor
-            .mtx
-            .lock()
-            .expect("StorageGuard Mutex poisoned before .drop()");
-
-        //This StorageGuard was giving exclusive Write access, so it is
-        //now safe to allow any type of access.
-        accessor_state.write_allowed = true;
-        accessor_state.read_allowed = true;
-
-        //Writer prioritization:
-        if accessor_state.writers_waiting > 0 {
-            self.accessor.writer_cvar.notify_one();
-        } else {
-            self.accessor.reader_cvar.notify_all();
-        }
+    #[cfg(feature = "metrics")]
+    pub(crate) fn lock_stats(&self) -> LockStats {
+        self.accessor.lock_stats()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn waiting(&self) -> (u16, u16, bool) {
+        self.accessor.waiting()
+    }
+
+    ///Kept in step with the keys of inner by MutableStorageGuard.
+    pub(super) fn mask(&self) -> &MaskBit {
+        &self.mask
+    }
+
+    pub(crate) fn mask_bit(&self) -> Option<u32> {
+        self.mask.bit()
+    }
+
+    pub(super) fn current_tick(&self) -> u64 {
+        self.world_tick.load(Ordering::Relaxed)
     }
 }
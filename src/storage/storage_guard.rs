@@ -8,12 +8,17 @@
 //-----------------------------------------------------------------------------
 
 use std::{
+    any::type_name,
     collections::{hash_map::Entry, HashMap},
+    mem::ManuallyDrop,
+    ops::{Index, IndexMut},
     sync::Arc,
+    time::Duration,
 };
 
 use super::super::{Component, Entity};
-use super::Storage;
+use super::held::{self, Held};
+use super::{ReadAccess, Storage};
 
 ///What you get when you ask the ECS for access to a Storage via req_read_access().
 ///These should NOT be held long-term. Do your work then allow this struct to drop, else
@@ -21,6 +26,7 @@ use super::Storage;
 #[derive(Debug)]
 pub struct ImmutableStorageGuard<T: Component> {
     guarded: Arc<Storage<T>>,
+    access: ReadAccess,
 }
 
 impl<T> ImmutableStorageGuard<T>
@@ -28,22 +34,137 @@ where
     T: Component,
 {
     pub(crate) fn new(guarded: Arc<Storage<T>>) -> Self {
-        guarded.init_read_access();
-        ImmutableStorageGuard { guarded }
+        held::check(guarded.held_id(), type_name::<T>(), Held::Read);
+        let access = guarded.accessor.init_read_access();
+        Self::granted(guarded, access)
+    }
+
+    pub(crate) fn try_new(guarded: Arc<Storage<T>>) -> Option<Self> {
+        if guarded.accessor.try_init_read_access() {
+            return Some(Self::granted(guarded, ReadAccess::Counted));
+        }
+
+        None
+    }
+
+    pub(crate) fn new_timeout(guarded: Arc<Storage<T>>, dur: Duration) -> Option<Self> {
+        if guarded.accessor.init_read_access_timeout(dur) {
+            return Some(Self::granted(guarded, ReadAccess::Counted));
+        }
+
+        None
+    }
+
+    fn granted(guarded: Arc<Storage<T>>, access: ReadAccess) -> Self {
+        held::acquired(guarded.held_id(), type_name::<T>(), Held::Read);
+        ImmutableStorageGuard { guarded, access }
     }
 
     pub fn get(&self, e: &Entity) -> Option<&T> {
         self.guarded.unsafe_borrow().get(e)
     }
 
+    ///True if the Entity has a Component in this Storage.
+    pub fn contains(&self, e: &Entity) -> bool {
+        self.guarded.unsafe_borrow().contains_key(e)
+    }
+
+    ///Like get(), for each Entity in turn; results are in the same order.
+    pub fn get_many(&self, entities: &[Entity]) -> Vec<Option<&T>> {
+        entities.iter().map(|e| self.get(e)).collect()
+    }
+
+    ///Like get_many(), but skips Entities without a Component and pairs the
+    ///rest with their Entity.
+    pub fn get_present<'a>(
+        &'a self,
+        entities: &'a [Entity],
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities.iter().filter_map(|e| Some((*e, self.get(e)?)))
+    }
+
+    ///The Component belonging to the lowest-indexed Entity, if any; meant for
+    ///Components expected to exist once, like a camera or player tag.
+    ///Scans the whole Storage, since Storages are unordered.
+    pub fn first(&self) -> Option<(Entity, &T)> {
+        self.find(|_| true)
+    }
+
+    ///Like first(), but only considers Components for which f returns true.
+    pub fn find<F: Fn(&T) -> bool>(&self, f: F) -> Option<(Entity, &T)> {
+        self.guarded
+            .unsafe_borrow()
+            .iter()
+            .filter(|(_, c)| f(c))
+            .min_by_key(|(e, _)| **e)
+            .map(|(e, c)| (*e, c))
+    }
+
+    ///Number of Components in this Storage. Storages only hold the Components
+    ///that actually exist, so this is O(1); no scan over empty slots occurs.
+    pub fn len(&self) -> usize {
+        self.guarded.unsafe_borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guarded.unsafe_borrow().is_empty()
+    }
+
+    ///Number of Components this Storage can hold before it must reallocate,
+    ///as for HashMap::capacity(). Storages are HashMaps keyed by Entity, not
+    ///Vecs indexed by it, so this is allocated but unused room, not a count
+    ///of slots per Entity; it's always at least len() and grows only when
+    ///len() would outgrow it, never because more Entities were created.
+    pub fn capacity(&self) -> usize {
+        self.guarded.unsafe_borrow().capacity()
+    }
+
+    ///Visits every Component in this Storage, in arbitrary order. Storages
+    ///hold no empty slots, so there are no Nones to filter out.
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.guarded.unsafe_borrow().values()
     }
 
+    ///Like iter(), but pairs each Component with the Entity it belongs to.
+    pub fn iter_entities(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.guarded.unsafe_borrow().iter().map(|(e, c)| (*e, c))
+    }
+
+    ///Visits only the Components inserted or mutably accessed at or after the
+    ///given tick (see World::current_tick()). A system that remembers the tick
+    ///it last ran at will see every change at least once.
+    ///
+    ///Mutable access counts as a change whether or not the Component was
+    ///actually modified.
+    pub fn iter_changed_since(&self, tick: u64) -> impl Iterator<Item = (Entity, &T)> {
+        let inner = self.guarded.unsafe_borrow();
+
+        self.guarded
+            .unsafe_borrow_changed()
+            .iter()
+            .filter(move |(_, changed)| **changed >= tick)
+            .filter_map(move |(e, _)| Some((*e, inner.get(e)?)))
+    }
+
     ///Favor using iter() or get() if at all possible.
     pub fn raw(&self) -> &HashMap<Entity, T> {
         self.guarded.unsafe_borrow()
     }
+
+    ///How many Entities have a change tick recorded, Component or not.
+    #[cfg(test)]
+    pub(crate) fn changed_rows(&self) -> usize {
+        self.guarded.unsafe_borrow_changed().len()
+    }
+
+    ///Deep-copies every Component out of this Storage, e.g. as an undo
+    ///checkpoint; see MutableStorageGuard::restore().
+    pub fn snapshot(&self) -> HashMap<Entity, T>
+    where
+        T: Clone,
+    {
+        self.guarded.unsafe_borrow().clone()
+    }
 }
 
 ///What you get when you ask the ECS for access to a Storage via req_write_access().
@@ -52,6 +173,8 @@ where
 #[derive(Debug)]
 pub struct MutableStorageGuard<T: Component> {
     guarded: Arc<Storage<T>>,
+    mask_dirty: bool, //Components may have come or gone untracked; see drop()
+    entered: Vec<Entity>, //stamped changed by entry(); unstamped in drop() if left vacant
 }
 
 impl<T> MutableStorageGuard<T>
@@ -59,39 +182,418 @@ where
     T: Component,
 {
     pub(crate) fn new(guarded: Arc<Storage<T>>) -> Self {
-        guarded.init_write_access();
-        MutableStorageGuard { guarded }
+        held::check(guarded.held_id(), type_name::<T>(), Held::Write);
+        guarded.accessor.init_write_access();
+        Self::granted(guarded)
+    }
+
+    pub(crate) fn try_new(guarded: Arc<Storage<T>>) -> Option<Self> {
+        if guarded.accessor.try_init_write_access() {
+            return Some(Self::granted(guarded));
+        }
+
+        None
     }
 
+    pub(crate) fn new_timeout(guarded: Arc<Storage<T>>, dur: Duration) -> Option<Self> {
+        if guarded.accessor.init_write_access_timeout(dur) {
+            return Some(Self::granted(guarded));
+        }
+
+        None
+    }
+
+    fn granted(guarded: Arc<Storage<T>>) -> Self {
+        held::acquired(guarded.held_id(), type_name::<T>(), Held::Write);
+        MutableStorageGuard {
+            guarded,
+            mask_dirty: false,
+            entered: Vec::new(),
+        }
+    }
+
+    ///The std HashMap Entry for the Entity's slot, with or_insert(),
+    ///or_insert_with(), and_modify() and friends. Counts as a change to that
+    ///Component for iter_changed_since(), unless the slot is left vacant.
+    ///
+    ///# Example
+    ///```
+    /// use ecs_it::{world::World, Component};
+    ///
+    /// #[derive(Component)]
+    /// struct DamageTaken(u32);
+    ///
+    /// let world = World::new();
+    /// world.register_component::<DamageTaken>();
+    /// let ent = world.create_entity();
+    ///
+    /// let mut guard = world.req_write_guard::<DamageTaken>();
+    /// for hit in [5, 3] {
+    ///     guard
+    ///         .entry(ent)
+    ///         .and_modify(|dmg| dmg.0 += hit)
+    ///         .or_insert(DamageTaken(hit));
+    /// }
+    /// guard.entry(ent).or_insert_with(|| DamageTaken(0)).0 += 1;
+    ///
+    /// assert_eq!(guard.raw()[&ent].0, 9);
+    ///```
     pub fn entry(&mut self, e: Entity) -> Entry<'_, Entity, T> {
+        self.mark_changed(e);
+        self.mask_dirty = true;
+        self.entered.push(e);
         self.guarded.unsafe_borrow_mut().entry(e)
     }
 
     ///User should perefer .entry() over this, the std Entry API is great.
-    pub fn get_mut(&self, e: &Entity) -> Option<&mut T> {
-        self.guarded.unsafe_borrow_mut().get_mut(e)
+    pub fn get_mut(&mut self, e: &Entity) -> Option<&mut T> {
+        let comp = self.guarded.unsafe_borrow_mut().get_mut(e)?;
+        self.mark_changed(*e);
+        Some(comp)
+    }
+
+    ///Moves the Entity's Component out, replaces it with f's result, and
+    ///returns true; for by-value transforms, e.g. swapping an enum variant,
+    ///that get_mut() can't express cleanly. Returns false, without calling f,
+    ///if the Entity has no Component here. If f panics, the Entity is left
+    ///without one.
+    pub fn modify<F: FnOnce(T) -> T>(&mut self, e: Entity, f: F) -> bool {
+        let inner = self.guarded.unsafe_borrow_mut();
+
+        match inner.remove(&e) {
+            Some(c) => {
+                //Unset meanwhile, so a panic in f leaves the mask accurate.
+                self.guarded.mask().unset(&e);
+                inner.insert(e, f(c));
+                self.guarded.mask().set(&e);
+                self.mark_changed(e);
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///Returns the Entity's Component, first inserting the result of f if it
+    ///doesn't have one.
+    pub fn get_or_insert_with<F: FnOnce() -> T>(&mut self, e: Entity, f: F) -> &mut T {
+        self.mark_changed(e);
+        self.guarded.mask().set(&e);
+        self.guarded.unsafe_borrow_mut().entry(e).or_insert_with(f)
+    }
+
+    ///Mutable access to the Components of several distinct Entities at once.
+    ///Returns None if any Entity is repeated or lacks a Component.
+    pub fn get_many_mut<const N: usize>(&mut self, entities: [Entity; N]) -> Option<[&mut T; N]> {
+        for (i, e) in entities.iter().enumerate() {
+            if entities[..i].contains(e) {
+                return None;
+            }
+        }
+
+        let comps = self
+            .guarded
+            .unsafe_borrow_mut()
+            .get_disjoint_mut(entities.each_ref());
+
+        if comps.iter().any(Option::is_none) {
+            return None;
+        }
+
+        for e in entities {
+            self.mark_changed(e);
+        }
+
+        Some(comps.map(|comp| comp.expect("checked above")))
     }
 
     pub fn insert(&mut self, e: Entity, c: T) -> Option<T> {
+        self.mark_changed(e);
+        self.guarded.mask().set(&e);
         self.guarded.unsafe_borrow_mut().insert(e, c)
     }
 
+    ///Like insert(), but also returns whether the Storage had to reallocate
+    ///to make room, e.g. for profiling. Storages are HashMaps keyed by
+    ///Entity, so this depends on how many Components are held, not on how
+    ///high the Entity's index is; see World::register_component_with_capacity().
+    pub fn insert_tracked(&mut self, e: Entity, c: T) -> (Option<T>, bool) {
+        let capacity = self.capacity();
+        let old = self.insert(e, c);
+
+        (old, self.capacity() > capacity)
+    }
+
+    ///Inserts every (Entity, T) pair, replacing and dropping any Components
+    ///already present.
+    pub fn insert_many<I: IntoIterator<Item = (Entity, T)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        self.guarded.unsafe_borrow_mut().reserve(iter.size_hint().0);
+
+        for (e, c) in iter {
+            self.insert(e, c);
+        }
+    }
+
+    ///Number of Components in this Storage. Storages only hold the Components
+    ///that actually exist, so this is O(1); no scan over empty slots occurs.
+    pub fn len(&self) -> usize {
+        self.guarded.unsafe_borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guarded.unsafe_borrow().is_empty()
+    }
+
+    ///Number of Components this Storage can hold before it must reallocate,
+    ///as for HashMap::capacity(). Storages are HashMaps keyed by Entity, not
+    ///Vecs indexed by it, so this is allocated but unused room, not a count
+    ///of slots per Entity; it's always at least len() and grows only when
+    ///len() would outgrow it, never because more Entities were created.
+    pub fn capacity(&self) -> usize {
+        self.guarded.unsafe_borrow().capacity()
+    }
+
+    ///Visits every Component in this Storage, in arbitrary order, marking
+    ///each as changed. Storages hold no empty slots, so there are no Nones
+    ///to filter out.
     pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.mark_all_changed();
         self.guarded.unsafe_borrow_mut().values_mut()
     }
 
-    pub fn raw_mut(&self) -> &mut HashMap<Entity, T> {
+    ///Like iter_mut(), but pairs each Component with the Entity it belongs to.
+    pub fn iter_entities_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+        self.mark_all_changed();
+        self.guarded.unsafe_borrow_mut().iter_mut().map(|(e, c)| (*e, c))
+    }
+
+    ///Like iter_entities_mut(), but visits, and marks changed, only those
+    ///Entities for which keep returns true.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn iter_entities_mut_where<'a, F>(
+        &'a mut self,
+        keep: F,
+    ) -> impl Iterator<Item = (Entity, &'a mut T)> + 'a
+    where
+        F: Fn(&Entity) -> bool + 'a,
+    {
+        let tick = self.guarded.current_tick();
+        let changed = self.guarded.unsafe_borrow_changed_mut();
+
+        self.guarded
+            .unsafe_borrow_mut()
+            .iter_mut()
+            .filter(move |(e, _)| keep(e))
+            .map(move |(e, c)| {
+                changed.insert(*e, tick);
+                (*e, c)
+            })
+    }
+
+    ///Favor using get_mut() or iter_mut() if at all possible.
+    pub fn raw(&self) -> &HashMap<Entity, T> {
+        self.guarded.unsafe_borrow()
+    }
+
+    ///Marks every Component as changed, since what happens to them through
+    ///the returned reference can't be tracked.
+    pub fn raw_mut(&mut self) -> &mut HashMap<Entity, T> {
+        self.mark_all_changed();
+        self.mask_dirty = true;
         self.guarded.unsafe_borrow_mut()
     }
 
     pub fn remove(&mut self, e: &Entity) -> Option<T> {
-        self.guarded.unsafe_borrow_mut().remove(e)
+        let removed = self.guarded.unsafe_borrow_mut().remove(e)?;
+        self.guarded.unsafe_borrow_changed_mut().remove(e);
+        self.guarded.mask().unset(e);
+        Some(removed)
     }
 
-    pub(crate) fn maintain_storage(&mut self, dead_entities: std::slice::Iter<'_, Entity>) {
-        for ent in dead_entities {
-            self.remove(ent);
+    ///Replaces the entire contents of this Storage with a snapshot from
+    ///ImmutableStorageGuard::snapshot(), returning the contents it replaced
+    ///so that the restore can itself be undone. Any snapshot is valid, since
+    ///Storages are keyed by Entity and have no fixed length, but Entities
+    ///removed since it was taken get their Components back until the next
+    ///World::maintain_ecs(). Every restored Component counts as changed.
+    pub fn restore(&mut self, snapshot: HashMap<Entity, T>) -> HashMap<Entity, T> {
+        self.guarded.unsafe_borrow_changed_mut().clear();
+        let replaced = std::mem::replace(self.guarded.unsafe_borrow_mut(), snapshot);
+
+        self.mark_all_changed();
+        self.guarded.mask().resync(self.guarded.unsafe_borrow().keys());
+
+        replaced
+    }
+
+    ///Swaps the Components of two Entities. Either or both may lack a
+    ///Component, in which case the Component (if any) simply changes owner.
+    pub fn swap(&mut self, a: Entity, b: Entity) {
+        if a == b {
+            return;
         }
+
+        let comp_a = self.remove(&a);
+        let comp_b = self.remove(&b);
+
+        if let Some(comp) = comp_a {
+            self.insert(b, comp);
+        }
+        if let Some(comp) = comp_b {
+            self.insert(a, comp);
+        }
+    }
+
+    ///Drops every Component for which f returns false. Components are visited
+    ///in ascending Entity order, so predicates may depend on that ordering.
+    pub fn retain<F: FnMut(Entity, &T) -> bool>(&mut self, mut f: F) {
+        let inner = self.guarded.unsafe_borrow_mut();
+
+        //HashMap::retain() visits in arbitrary order, so sort first.
+        let mut ents: Vec<Entity> = inner.keys().copied().collect();
+        ents.sort_unstable();
+
+        for e in ents {
+            if !f(e, &inner[&e]) {
+                inner.remove(&e);
+                self.guarded.unsafe_borrow_changed_mut().remove(&e);
+                self.guarded.mask().unset(&e);
+            }
+        }
+    }
+
+    ///Moves every Component out of this Storage, paired with its Entity. The
+    ///Storage is empty once the iterator is dropped, even if it wasn't fully
+    ///consumed; unconsumed Components are dropped. Capacity is kept.
+    pub fn drain(&mut self) -> impl Iterator<Item = (Entity, T)> + '_ {
+        self.guarded.unsafe_borrow_changed_mut().clear();
+        self.guarded.mask().resync(std::iter::empty());
+        self.guarded.unsafe_borrow_mut().drain()
+    }
+
+    ///Drops every Component in this Storage. Entities are unaffected, and the
+    ///Storage keeps its allocated capacity.
+    pub fn clear(&mut self) {
+        self.guarded.unsafe_borrow_changed_mut().clear();
+        self.guarded.mask().resync(std::iter::empty());
+        self.guarded.unsafe_borrow_mut().clear()
+    }
+
+    ///Releases as much spare capacity as possible; see also
+    ///World::shrink_storages().
+    pub fn shrink_to_fit(&mut self) {
+        self.guarded.unsafe_borrow_mut().shrink_to_fit();
+        self.guarded.unsafe_borrow_changed_mut().shrink_to_fit();
+    }
+
+    fn mark_changed(&self, e: Entity) {
+        let tick = self.guarded.current_tick();
+        self.guarded.unsafe_borrow_changed_mut().insert(e, tick);
+    }
+
+    fn mark_all_changed(&self) {
+        let tick = self.guarded.current_tick();
+        let changed = self.guarded.unsafe_borrow_changed_mut();
+
+        for e in self.guarded.unsafe_borrow().keys() {
+            changed.insert(*e, tick);
+        }
+    }
+}
+
+///What you get when you ask the ECS for access to a Storage via
+///req_upgradable_guard(). Grants read access alongside any number of plain
+///ImmutableStorageGuards, but only one UpgradableStorageGuard may exist per
+///Storage at a time. Call upgrade() to trade it for a MutableStorageGuard
+///without any other writer getting access in between.
+#[derive(Debug)]
+pub struct UpgradableStorageGuard<T: Component> {
+    guarded: Arc<Storage<T>>,
+}
+
+impl<T> UpgradableStorageGuard<T>
+where
+    T: Component,
+{
+    pub(crate) fn new(guarded: Arc<Storage<T>>) -> Self {
+        held::check(guarded.held_id(), type_name::<T>(), Held::Upgradable);
+        guarded.accessor.init_upgradable_access();
+        held::acquired(guarded.held_id(), type_name::<T>(), Held::Upgradable);
+        UpgradableStorageGuard { guarded }
+    }
+
+    pub fn get(&self, e: &Entity) -> Option<&T> {
+        self.guarded.unsafe_borrow().get(e)
+    }
+
+    pub fn contains(&self, e: &Entity) -> bool {
+        self.guarded.unsafe_borrow().contains_key(e)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.guarded.unsafe_borrow().values()
+    }
+
+    ///Favor using iter() or get() if at all possible.
+    pub fn raw(&self) -> &HashMap<Entity, T> {
+        self.guarded.unsafe_borrow()
+    }
+
+    ///Blocks until every other reader has dropped, then returns exclusive
+    ///write access. Readers requesting access after this call must wait for
+    ///the returned MutableStorageGuard to drop.
+    pub fn upgrade(self) -> MutableStorageGuard<T> {
+        //Our Drop impl must not run, since the read access it would release
+        //is being converted rather than released.
+        let this = ManuallyDrop::new(self);
+        //Safety: `this` is never dropped nor used again, so the Arc is moved
+        //out of it exactly once.
+        let guarded = unsafe { std::ptr::read(&this.guarded) };
+
+        held::released(guarded.held_id(), Held::Upgradable);
+        guarded.accessor.upgrade_access();
+        MutableStorageGuard::granted(guarded)
+    }
+}
+
+//guard[entity] works like Vec indexing: it panics if the Entity has no
+//Component here. Prefer get() and get_mut() wherever it might not.
+
+fn missing<T>(e: &Entity) -> ! {
+    panic!("{} has no {} Component", e, type_name::<T>())
+}
+
+impl<T: Component> Index<Entity> for ImmutableStorageGuard<T> {
+    type Output = T;
+
+    fn index(&self, e: Entity) -> &T {
+        self.get(&e).unwrap_or_else(|| missing::<T>(&e))
+    }
+}
+
+impl<T: Component> Index<Entity> for UpgradableStorageGuard<T> {
+    type Output = T;
+
+    fn index(&self, e: Entity) -> &T {
+        self.get(&e).unwrap_or_else(|| missing::<T>(&e))
+    }
+}
+
+impl<T: Component> Index<Entity> for MutableStorageGuard<T> {
+    type Output = T;
+
+    fn index(&self, e: Entity) -> &T {
+        self.guarded
+            .unsafe_borrow()
+            .get(&e)
+            .unwrap_or_else(|| missing::<T>(&e))
+    }
+}
+
+///Marks the Component changed, like get_mut().
+impl<T: Component> IndexMut<Entity> for MutableStorageGuard<T> {
+    fn index_mut(&mut self, e: Entity) -> &mut T {
+        self.get_mut(&e).unwrap_or_else(|| missing::<T>(&e))
     }
 }
 
@@ -100,7 +602,18 @@ where
     T: Component,
 {
     fn drop(&mut self) {
-        self.guarded.drop_read_access();
+        held::released(self.guarded.held_id(), Held::Read);
+        self.guarded.accessor.drop_read_access(self.access);
+    }
+}
+
+impl<T> Drop for UpgradableStorageGuard<T>
+where
+    T: Component,
+{
+    fn drop(&mut self) {
+        held::released(self.guarded.held_id(), Held::Upgradable);
+        self.guarded.accessor.drop_upgradable_access();
     }
 }
 
@@ -109,6 +622,18 @@ where
     T: Component,
 {
     fn drop(&mut self) {
-        self.guarded.drop_write_access();
+        //Must happen before other threads can see the Storage.
+        if self.mask_dirty {
+            let inner = self.guarded.unsafe_borrow();
+            self.guarded.mask().resync(inner.keys());
+
+            let changed = self.guarded.unsafe_borrow_changed_mut();
+            for e in self.entered.iter().filter(|e| !inner.contains_key(e)) {
+                changed.remove(e);
+            }
+        }
+
+        held::released(self.guarded.held_id(), Held::Write);
+        self.guarded.accessor.drop_write_access();
     }
 }
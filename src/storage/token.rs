@@ -0,0 +1,54 @@
+//-----------------------------------------------------------------------------
+//------------------- Storage Locks Not Released on Drop ----------------------
+//-----------------------------------------------------------------------------
+
+use std::sync::Arc;
+
+use super::super::Component;
+use super::{Accessor, ReadAccess, Storage};
+
+///Lets a StorageToken reach the Accessor of a Storage<T> without knowing T.
+trait Locked: Send + Sync {
+    fn accessor(&self) -> &Accessor;
+}
+
+impl<T: Component> Locked for Storage<T> {
+    fn accessor(&self) -> &Accessor {
+        &self.accessor
+    }
+}
+
+///Read access to one Storage, taken by World::lock_storage_read() and given
+///back only by World::unlock_storage(). Dropping a StorageToken does NOT
+///give it back: the Storage then stays read-locked for good, and every
+///writer of it blocks forever.
+#[must_use = "dropping a StorageToken leaves its Storage locked forever"]
+pub struct StorageToken {
+    locked: Arc<dyn Locked>,
+    access: ReadAccess,
+}
+
+impl std::fmt::Debug for StorageToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StorageToken")
+            .field("access", &self.access)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StorageToken {
+    ///Blocks like ImmutableStorageGuard::new(), but skips the debug-only
+    ///held-guard tracking, since the token may be unlocked on another thread.
+    pub(crate) fn lock_read<T: Component>(storage: Arc<Storage<T>>) -> Self {
+        let access = storage.accessor.init_read_access();
+
+        StorageToken {
+            locked: storage,
+            access,
+        }
+    }
+
+    pub(crate) fn unlock(self) {
+        self.locked.accessor().drop_read_access(self.access);
+    }
+}
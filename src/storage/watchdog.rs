@@ -0,0 +1,59 @@
+//-----------------------------------------------------------------------------
+//---------------------- Runtime Reader Starvation Reports --------------------
+//-----------------------------------------------------------------------------
+
+//Under AccessPriority::Writers (the default), a reader can wait indefinitely
+//while writers keep arriving. With the starvation-watchdog feature, a reader
+//that has waited longer than a threshold is reported, once per request, so
+//starvation shows up in the logs instead of as a silent hang. The threshold
+//and report are process-wide, like a logger, rather than per World.
+
+use std::{
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+const WATCHDOG_POISON: &str = "watchdog RwLock found poisoned in watchdog.rs";
+
+///Used until set_starvation_watchdog() is called.
+const DEFAULT_THRESHOLD: Duration = Duration::from_secs(1);
+
+type OnStarved = Arc<dyn Fn(&'static str, Duration) + Send + Sync>;
+
+static WATCHDOG: RwLock<Option<(Duration, OnStarved)>> = RwLock::new(None);
+
+///Replaces the starvation watchdog's threshold and report. on_starved is
+///called with the type name of the Storage (or Resource, or event queue) and
+///how long the reader had waited, from the starved reader's own thread and
+///without holding any lock. Until this is called, readers waiting longer
+///than one second are reported to stderr. Requires the starvation-watchdog
+///feature.
+pub fn set_starvation_watchdog(
+    threshold: Duration,
+    on_starved: impl Fn(&'static str, Duration) + Send + Sync + 'static,
+) {
+    *WATCHDOG.write().expect(WATCHDOG_POISON) = Some((threshold, Arc::new(on_starved)));
+}
+
+pub(super) fn threshold() -> Duration {
+    match &*WATCHDOG.read().expect(WATCHDOG_POISON) {
+        Some((threshold, _)) => *threshold,
+        None => DEFAULT_THRESHOLD,
+    }
+}
+
+pub(super) fn report(type_name: &'static str, waited: Duration) {
+    let on_starved = WATCHDOG
+        .read()
+        .expect(WATCHDOG_POISON)
+        .as_ref()
+        .map(|(_, on_starved)| Arc::clone(on_starved));
+
+    match on_starved {
+        Some(on_starved) => on_starved(type_name, waited),
+        None => eprintln!(
+            "ecs_it: a reader of {} has been starved for {:?} by writers",
+            type_name, waited
+        ),
+    }
+}
@@ -2,57 +2,252 @@
 //June 15, 2022
 
 use std::{
-    any::TypeId, //TypeId::of<T>() -> TypeId;
-    collections::HashMap,
-    sync::{Arc, Mutex, MutexGuard},
+    any::{type_name, Any, TypeId}, //TypeId::of<T>() -> TypeId;
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::Duration,
 };
 
+#[cfg(feature = "serde")]
+use super::snapshot::{SerdeFns, SERDE_FN_POISON};
+#[cfg(feature = "metrics")]
+use super::storage::LockStats;
 use super::{
-    entity::Entities,
-    storage::{ImmutableStorageGuard, MutableStorageGuard, Storage, StorageBox},
+    entity::{self, Entities, RecyclePolicy},
+    error::ECSError,
+    frame_access::FrameAccess,
+    join::{acquisition_order, Join, JoinQuery},
+    storage::{
+        AccessPriority, BackBuffer, ComponentMask, EntityMasks, ImmutableStorageGuard, MaskBit,
+        MutableStorageGuard, Storage, StorageBox, StorageToken, UpgradableStorageGuard,
+        MAX_MASK_BITS,
+    },
     Component,
-    Entity, //usize
+    Entity,
 };
 
 const STORAGE_POISON: &str = "storages mtx found poisoned in world.rs";
 const ENTITIES_POISON: &str = "Entities mtx found poisoned in world.rs";
 const MAINTENANCE_FN_POISON: &str = "maintenance_fns mtx found poisoned in world.rs";
+const NAMED_POISON: &str = "named_components mtx found poisoned in world.rs";
+const DESCRIBE_FN_POISON: &str = "describe_fns mtx found poisoned in world.rs";
+const BACK_BUFFER_POISON: &str = "back_buffers mtx found poisoned in world.rs";
+const ENTITY_LIMIT_MSG: &str = "reached the World's living Entity limit";
 
 ///The core of the library; must instantiate (via World::new()).
 pub struct World {
     //Arc<World>
-    pub(crate) entities: Mutex<Entities>,
+    //Always lock via lock_entities(), so reserved Entities are accounted for.
+    entities: Mutex<Entities>,
+    fresh_indices: AtomicU32, //next never-used Entity index; see reserve_entity()
+    entity_limit: usize,      //max living Entities; see with_entity_limit()
     storages: Mutex<HashMap<TypeId, StorageBox>>,
-    maintenance_fns: Mutex<Vec<Box<dyn Fn(&World, &Entity)>>>,
+    back_buffers: Mutex<HashMap<TypeId, StorageBox>>, //see register_double_buffered()
+    maintenance_fns: Mutex<BTreeMap<TypeId, MaintenanceFn>>,
+    describe_fns: Mutex<BTreeMap<TypeId, DescribeFn>>,
+    named_components: Mutex<HashMap<String, (TypeId, RemoveFn)>>,
+    tick: Arc<AtomicU64>, //shared with every Storage, for change detection
+    masks: Arc<EntityMasks>, //shared with every Storage; see component_mask()
+    next_mask_bit: AtomicU32,
+    pub(crate) resources: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+    pub(crate) events: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+    pub(crate) hooks: Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>, //see on_insert()
+    #[cfg(feature = "serde")]
+    pub(crate) serde_fns: Mutex<BTreeMap<&'static str, SerdeFns>>,
+}
+
+type MaintenanceFn = Box<dyn Fn(&World, &[Entity]) + Send + Sync>;
+type DescribeFn = fn(&World, Entity) -> Option<String>;
+type RemoveFn = fn(&World, Entity) -> bool;
+type ContainsFn = fn(&World, Entity) -> bool;
+
+///Settings fixed when a World is created; see World::new_with_config().
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldConfig {
+    ///Which dead Entity ID create_entity() reuses first.
+    pub recycling: RecyclePolicy,
+    ///Max living Entities; see World::with_entity_limit().
+    pub entity_limit: usize,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        WorldConfig {
+            recycling: RecyclePolicy::default(),
+            entity_limit: usize::MAX,
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
 }
 
 impl World {
     pub fn new() -> Self {
         World {
             entities: Mutex::new(Entities::new()),
+            fresh_indices: AtomicU32::new(0),
+            entity_limit: usize::MAX,
             storages: Mutex::new(HashMap::new()),
-            maintenance_fns: Mutex::new(Vec::new()),
+            back_buffers: Mutex::new(HashMap::new()),
+            maintenance_fns: Mutex::new(BTreeMap::new()),
+            describe_fns: Mutex::new(BTreeMap::new()),
+            named_components: Mutex::new(HashMap::new()),
+            tick: Arc::new(AtomicU64::new(0)),
+            masks: Arc::new(EntityMasks::default()),
+            next_mask_bit: AtomicU32::new(0),
+            resources: Mutex::new(HashMap::new()),
+            events: Mutex::new(HashMap::new()),
+            hooks: Mutex::new(HashMap::new()),
+            #[cfg(feature = "serde")]
+            serde_fns: Mutex::new(BTreeMap::new()),
         }
     }
 
+    ///Like new(), but no more than limit Entities may be alive at once, to
+    ///bound memory use. Removed Entities stop counting against the limit
+    ///right away, so their IDs never block new ones.
+    pub fn with_entity_limit(limit: usize) -> Self {
+        World::new_with_config(WorldConfig {
+            entity_limit: limit,
+            ..WorldConfig::default()
+        })
+    }
+
+    ///Like new(), but with the given settings; new() uses WorldConfig's
+    ///defaults.
+    pub fn new_with_config(config: WorldConfig) -> Self {
+        let mut world = World {
+            entity_limit: config.entity_limit,
+            ..World::new()
+        };
+
+        world
+            .entities
+            .get_mut()
+            .expect(ENTITIES_POISON)
+            .set_recycling(config.recycling);
+
+        world
+    }
+
     ///Inserts a "blank" Entity into the World. You need to call
     ///add_component() to allow this Entity to do/be anything of
-    ///substance. Returns the entity ID, which is a usize, which
-    ///is type-aliased as "Entity" in this library.
+    ///substance. Returns the new Entity, a Copy-able generational index.
+    ///
+    ///This always locks the Entities list, even when it ends up minting a
+    ///fresh index, because dead indices are reused first: whether one is free
+    ///can only be known under that lock, and deciding to mint must happen
+    ///under the same lock so the entity limit can't be raced past either. For
+    ///lock-free minting that never recycles, see reserve_entity().
+    /// ## Panics
+    /// Panics if the World's Entity limit is reached; see with_entity_limit().
     pub fn create_entity(&self) -> Entity {
-        let id = self
-            .entities
-            .lock()
-            .expect("entities mtx found poisoned in World::init_entity()")
-            .new_entity_id();
+        self.try_create_entity().expect(ENTITY_LIMIT_MSG)
+    }
 
-        id
+    ///Non-panicking counterpart to create_entity(). Returns Err, creating
+    ///nothing, if the World's Entity limit is already reached.
+    pub fn try_create_entity(&self) -> Result<Entity, ECSError> {
+        Ok(self.try_create_entities(1)?[0])
     }
 
-    /// Clones all existing Entities into an UNSORTED Vec, then returns an
+    ///Like create_entity(), but creates n Entities under a single lock of the
+    ///Entities list. IDs of removed-and-maintained Entities are reused first.
+    /// ## Panics
+    /// Panics if creating n Entities would exceed the World's Entity limit.
+    pub fn create_entities(&self, n: usize) -> Vec<Entity> {
+        self.try_create_entities(n).expect(ENTITY_LIMIT_MSG)
+    }
+
+    ///Non-panicking counterpart to create_entities(). Returns Err, creating
+    ///none of them, if n more Entities would exceed the World's Entity limit.
+    pub fn try_create_entities(&self, n: usize) -> Result<Vec<Entity>, ECSError> {
+        let mut entities_guard = self.lock_entities();
+
+        if entities_guard.living_count().saturating_add(n) > self.entity_limit {
+            return Err(ECSError::LimitReached {
+                limit: self.entity_limit,
+            });
+        }
+
+        Ok((0..n)
+            .map(|_| {
+                let ent = entities_guard.new_entity_id(&self.fresh_indices);
+                self.masks.claim(&ent);
+                ent
+            })
+            .collect())
+    }
+
+    ///Like create_entity(), but never locks the Entities list: a brand-new
+    ///index is taken with a single atomic op, for loaders that spawn from
+    ///many threads at once. The price is that removed Entities' indices are
+    ///never reused this way, so mixing in create_entity() keeps indices from
+    ///growing without bound in long-running games with high turnover.
+    ///
+    ///The Entity is alive as soon as this returns; it is recorded in the
+    ///Entities list the next time anything locks that list.
+    ///
+    ///Returns Err, reserving nothing, if the limit set by with_entity_limit()
+    ///is already reached. Checking that means locking the Entities list after
+    ///all, so a World with a limit loses the lock-free fast path; one built
+    ///without a limit never locks it here.
+    pub fn reserve_entity(&self) -> Result<Entity, ECSError> {
+        if self.entity_limit == usize::MAX {
+            return Ok(Entity::new(entity::mint_index(&self.fresh_indices), 0));
+        }
+
+        //Held while minting, so that no other thread can take the last slot
+        //between the check and the mint.
+        let entities_guard = self.lock_entities();
+
+        if entities_guard.living_count() >= self.entity_limit {
+            return Err(ECSError::LimitReached {
+                limit: self.entity_limit,
+            });
+        }
+
+        Ok(Entity::new(entity::mint_index(&self.fresh_indices), 0))
+    }
+
+    ///Locks the Entities list, first recording any Entities handed out by
+    ///reserve_entity() since the last lock.
+    pub(crate) fn lock_entities(&self) -> MutexGuard<'_, Entities> {
+        let mut entities_guard = self.entities.lock().expect(ENTITIES_POISON);
+        entities_guard.flush_reserved(self.fresh_indices.load(Ordering::Acquire));
+        entities_guard
+    }
+
+    ///Swaps in a whole new Entities list, e.g. from a snapshot, and rewinds
+    ///the high-water mark to match it. The World's RecyclePolicy is kept.
+    ///Entities already alive here keep their ComponentMasks, so any of their
+    ///Components must be cleared first if the new list doesn't have them.
+    pub(crate) fn replace_entities(&self, mut entities: Entities) {
+        let mut entities_guard = self.entities.lock().expect(ENTITIES_POISON);
+        entities.set_recycling(entities_guard.recycling());
+        self.fresh_indices
+            .store(entities.num_entities(), Ordering::Release);
+
+        for ent in entities.with_components() {
+            self.masks.claim(&ent);
+        }
+
+        *entities_guard = entities;
+    }
+
+    /// Clones all living Entities into a Vec sorted by index, then returns an
     /// iterator over that Vec; does not consume the underlying data structure.
+    /// Removed Entities are skipped, even before World::maintain_ecs().
     ///
-    /// Reminder: an Entity is just a usize - nothing more.
+    /// Reminder: an Entity is just a generational index - nothing more.
     ///
     ///# Example
     ///```
@@ -69,14 +264,61 @@ impl World {
     /// }
     ///```
     pub fn entity_iter(&self) -> impl Iterator<Item = Entity> {
-        let entities_guard: MutexGuard<Entities> = self.entities.lock().expect(ENTITIES_POISON);
+        let entities_guard: MutexGuard<Entities> = self.lock_entities();
         entities_guard.vec().into_iter()
     }
 
+    ///Number of living Entities. Removed Entities stop counting immediately,
+    ///even before World::maintain_ecs().
+    pub fn entity_count(&self) -> usize {
+        self.lock_entities().living_count()
+    }
+
+    ///Number of removed Entities whose IDs are waiting to be reused: those
+    ///already cleared by maintain_ecs(), plus those still awaiting it. Useful
+    ///for deciding when maintain_ecs() is worth calling.
+    pub fn dead_entity_count(&self) -> usize {
+        self.lock_entities().dead_count()
+    }
+
+    ///True if any Entity has been removed since the last maintain_ecs(), so
+    ///its Components are still waiting to be cleared.
+    pub fn pending_maintenance(&self) -> bool {
+        self.lock_entities().has_unmaintained()
+    }
+
+    ///True if the Entity has been created and not yet removed. A stale handle
+    ///to a removed Entity stays dead even after its index is recycled.
+    pub fn is_alive(&self, e: Entity) -> bool {
+        self.lock_entities().is_alive(&e)
+    }
+
     ///When entities "die" or otherwise need to be removed from the game world,
-    ///this is the fn to call. See: World::maintain_ecs()
+    ///this is the fn to call. The Entity's Components are not dropped, and its
+    ///ID is not reused, until the next call to World::maintain_ecs().
+    ///
+    ///Removal is lazy: the Entity stops being alive (see is_alive() and
+    ///entity_iter()) immediately, but its Components stay in their Storages,
+    ///visible to guards and has_component(), until maintain_ecs() clears them.
     pub fn rm_entity(&self, e: Entity) {
-        self.entities.lock().expect(ENTITIES_POISON).rm_entity(e);
+        self.lock_entities().rm_entity(e);
+    }
+
+    ///Like rm_entity(), but every Component of the Entity is dropped before
+    ///this returns, rather than at the next maintain_ecs(). Write-locks every
+    ///Storage in turn, so it costs about as much as a maintain_ecs() call;
+    ///prefer rm_entity() unless lingering Components are a problem.
+    ///
+    ///Does nothing if the Entity isn't alive.
+    pub fn despawn_now(&self, e: Entity) {
+        if !self.lock_entities().rm_entity_unqueued(e) {
+            return;
+        }
+
+        //The ID isn't recycled until its Components are gone, so no new
+        //Entity can pick them up in the meantime.
+        self.clear_components(&[e]);
+        self.lock_entities().recycle(vec![e]);
     }
 
     ///Component types must be registered with the ECS before use. This fn also
@@ -87,53 +329,806 @@ impl World {
     /// ## Panics
     /// Panics if you register the same component type twice.
     pub fn register_component<T: Component>(&self) {
-        let type_id = TypeId::of::<T>();
+        self.register_component_with_priority::<T>(AccessPriority::default());
+    }
 
-        let mut storages_guard: MutexGuard<'_, HashMap<TypeId, StorageBox>> =
-            self.storages.lock().expect(STORAGE_POISON);
+    ///Like register_component(), but lets you choose whether the Storage of T
+    ///favors readers or writers under contention. register_component() uses
+    ///AccessPriority::Writers.
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type twice.
+    pub fn register_component_with_priority<T: Component>(&self, priority: AccessPriority) {
+        if self.try_register::<T>(priority, 0).is_err() {
+            panic!("attempted to register the same component type twice");
+        }
+    }
 
-        if storages_guard.contains_key(&type_id) {
+    ///Like register_component(), but the Storage of T starts out able to hold
+    ///capacity Components without reallocating. Worth it when you know roughly
+    ///how many Entities will have a T, e.g. when loading a level.
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type twice.
+    pub fn register_component_with_capacity<T: Component>(&self, capacity: usize) {
+        if self.try_register::<T>(AccessPriority::default(), capacity).is_err() {
             panic!("attempted to register the same component type twice");
         }
+    }
+
+    ///Registers T just like register_component(), plus a second, back buffer
+    ///Storage of T, e.g. to keep last frame's transforms for interpolation.
+    ///Guards and the rest of the World API see the front buffer; see
+    ///req_read_guard_back() and swap_buffers() for the back buffer. Entities
+    ///removed via rm_entity() lose their Components in both.
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type twice.
+    pub fn register_double_buffered<T: Component>(&self) {
+        self.register_component::<T>();
+
+        fn maintain_back<T: Component>(world: &World, dead: &[Entity]) {
+            let mut back_guard = MutableStorageGuard::new(world.back_buffer::<T>());
+            for entity in dead {
+                back_guard.remove(entity);
+            }
+        }
+
+        fn shrink_back<T: Component>(world: &World) {
+            MutableStorageGuard::new(world.back_buffer::<T>()).shrink_to_fit();
+        }
+
+        fn clear_back<T: Component>(world: &World) {
+            MutableStorageGuard::new(world.back_buffer::<T>()).clear();
+        }
+
+        //The back buffer gets no ComponentMask bit; masks follow the front.
+        let back = Storage::<T>::new(
+            AccessPriority::default(),
+            Arc::clone(&self.tick),
+            0,
+            MaskBit::new(Arc::clone(&self.masks), None),
+        );
 
-        let should_be_none = storages_guard.insert(
-            type_id,
+        self.back_buffers.lock().expect(BACK_BUFFER_POISON).insert(
+            TypeId::of::<T>(),
             StorageBox {
-                boxed: Arc::new(Storage::<T>::new()),
+                boxed: Arc::new(back.into_back_buffer()),
+                shrink: shrink_back::<T>,
+                clear: clear_back::<T>,
+                contains: None,
             },
         );
 
-        assert!(should_be_none.is_none());
+        self.push_maintenance_fn(
+            TypeId::of::<BackBuffer<T>>(),
+            Box::new(maintain_back::<T>),
+        );
+    }
+
+    ///Swaps the contents of the front and back buffers of T, e.g. at the
+    ///start of each frame. This is O(1), but the front then holds whatever
+    ///the back held, so overwrite it before reading it as this frame's state.
+    ///Blocks until no guard over either buffer remains.
+    ///## Panics
+    ///Panics if T wasn't registered via register_double_buffered().
+    pub fn swap_buffers<T: Component>(&self) {
+        self.storage::<T>().swap_buffers(&self.back_buffer::<T>());
+    }
+
+    ///Like req_read_guard(), but over the back buffer of T; see
+    ///register_double_buffered(). To hold guards over both buffers at once,
+    ///take the front buffer's first, as swap_buffers() does.
+    ///## Panics
+    ///Panics if T wasn't registered via register_double_buffered().
+    pub fn req_read_guard_back<T: Component>(&self) -> ImmutableStorageGuard<T> {
+        ImmutableStorageGuard::new(self.back_buffer::<T>())
+    }
+
+    fn back_buffer<T: Component>(&self) -> Arc<Storage<T>> {
+        let back = self
+            .back_buffers
+            .lock()
+            .expect(BACK_BUFFER_POISON)
+            .get(&TypeId::of::<T>())
+            .map(|storage_box| storage_box.clone_storage::<T>());
+
+        match back {
+            Some(back) => back,
+            None => panic!(
+                "{} has no back buffer; register it via register_double_buffered()",
+                type_name::<T>()
+            ),
+        }
+    }
+
+    ///Registers T just like register_component(), and also lets
+    ///describe_entity() list it, Debug-formatted, on any Entity that has one.
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type twice.
+    pub fn register_debug_component<T: Component + Debug>(&self) {
+        self.register_component::<T>();
+
+        fn describe<T: Component + Debug>(world: &World, ent: Entity) -> Option<String> {
+            world.req_read_guard::<T>().get(&ent).map(|comp| format!("{:?}", comp))
+        }
+
+        self.describe_fns
+            .lock()
+            .expect(DESCRIBE_FN_POISON)
+            .insert(TypeId::of::<T>(), describe::<T>);
+    }
+
+    ///Registers T just like register_component(), and also names it, so that
+    ///tools which only know Component types at runtime, such as level editors,
+    ///can operate on it; see rm_component_by_type().
+    ///
+    /// ## Panics
+    /// Panics if you register the same component type or name twice.
+    pub fn register_component_named<T: Component>(&self, name: &str) {
+        let mut named = self.named_components.lock().expect(NAMED_POISON);
+
+        if named.contains_key(name) {
+            drop(named);
+            panic!("attempted to register the same component name twice: {}", name);
+        }
+
+        if self.try_register_component::<T>().is_err() {
+            drop(named);
+            panic!("attempted to register the same component type twice");
+        }
+
+        fn remove<T: Component>(world: &World, ent: Entity) -> bool {
+            world.rm_component::<T>(&ent).is_some()
+        }
+
+        named.insert(name.to_string(), (TypeId::of::<T>(), remove::<T>));
+    }
+
+    ///Removes the Entity's Component of the type registered under the name
+    ///via register_component_named(). Returns whether there was one to
+    ///remove; an unknown name removes nothing.
+    pub fn rm_component_by_type(&self, type_name: &str, e: Entity) -> bool {
+        let remove = self
+            .named_components
+            .lock()
+            .expect(NAMED_POISON)
+            .get(type_name)
+            .map(|(_, remove)| *remove);
+
+        remove.is_some_and(|remove| remove(self, e))
+    }
+
+    ///Debug-formats every Component the Entity has whose type was registered
+    ///via register_debug_component(); meant for debug inspectors. Storages
+    ///are read one at a time, in TypeId order.
+    pub fn describe_entity(&self, e: Entity) -> Vec<String> {
+        //Copied out, so that registering a Component doesn't have to wait
+        //on the Storage reads below.
+        let describe_fns: Vec<DescribeFn> = self
+            .describe_fns
+            .lock()
+            .expect(DESCRIBE_FN_POISON)
+            .values()
+            .copied()
+            .collect();
+
+        describe_fns.into_iter().filter_map(|f| f(self, e)).collect()
+    }
+
+    ///The TypeId of every registered Component type the Entity has, in
+    ///TypeId order; complements describe_entity(), but covers every type.
+    ///Storages are read one at a time, so don't call this while holding a
+    ///write guard.
+    pub fn component_types(&self, e: Entity) -> Vec<TypeId> {
+        let mut contains_fns: Vec<(TypeId, ContainsFn)> = self
+            .storages
+            .lock()
+            .expect(STORAGE_POISON)
+            .iter()
+            .filter_map(|(type_id, storage_box)| Some((*type_id, storage_box.contains?)))
+            .collect();
+        contains_fns.sort_unstable_by_key(|(type_id, _)| *type_id);
+
+        contains_fns
+            .into_iter()
+            .filter(|(_, contains)| contains(self, e))
+            .map(|(type_id, _)| type_id)
+            .collect()
+    }
+
+    ///Non-panicking counterpart to register_component(). Returns Err, leaving
+    ///the existing Storage untouched, if T is already registered, so plugins
+    ///can each register the Components they use without coordinating.
+    pub fn try_register_component<T: Component>(&self) -> Result<(), ECSError> {
+        self.try_register::<T>(AccessPriority::default(), 0)
+    }
+
+    fn try_register<T: Component>(
+        &self,
+        priority: AccessPriority,
+        capacity: usize,
+    ) -> Result<(), ECSError> {
+        self.try_insert_storage::<T>(priority, capacity)?;
 
         //Generate Fn to be called in world.maintain_ecs() & store it in World
-        fn maintain_storage<T>(world: &World, entity: &Entity) where T: Component {
+        fn maintain_storage<T>(world: &World, dead: &[Entity]) where T: Component {
+            let hooks = world.hooks::<T>();
             let mut mut_guard = world.req_write_guard::<T>();
-            mut_guard.remove(entity);
+            for entity in dead {
+                hooks.remove(&mut mut_guard, entity);
+            }
         }
 
+        self.push_maintenance_fn(TypeId::of::<T>(), Box::new(maintain_storage::<T>));
+
+        Ok(())
+    }
+
+    ///Removes the Storage of T, dropping every Component of type T, along with
+    ///its maintenance fn, hooks, and any debug, name, or serde registration.
+    ///Hooks don't fire for the dropped Components. T may be
+    ///registered again afterwards. Entities are untouched: their indices stay
+    ///valid, as do their Components of other types.
+    ///
+    ///Returns Err, changing nothing, if T is unregistered, or if any guard,
+    ///Join, or FrameAccess over the Storage of T still exists. This never
+    ///waits for such guards to drop; it only briefly takes internal locks.
+    pub fn unregister_component<T: Component>(&self) -> Result<(), ECSError> {
+        let type_id = TypeId::of::<T>();
+
+        //Locked in the same order that registration, save()/load(), and
+        //maintain_ecs() take them, so none of those can deadlock with this
+        //or observe T half-removed.
+        #[cfg(feature = "serde")]
+        let mut serde_fns = self.serde_fns.lock().expect(SERDE_FN_POISON);
+        let mut named = self.named_components.lock().expect(NAMED_POISON);
+        let mut describe_fns = self.describe_fns.lock().expect(DESCRIBE_FN_POISON);
+        let mut maint_fns = self
+            .maintenance_fns
+            .lock()
+            .expect(MAINTENANCE_FN_POISON);
+        let mut storages_guard = self.storages.lock().expect(STORAGE_POISON);
+
+        let storage_box = storages_guard
+            .get(&type_id)
+            .ok_or(ECSError::Unregistered {
+                type_name: type_name::<T>(),
+            })?;
+
+        let mut back_buffers = self.back_buffers.lock().expect(BACK_BUFFER_POISON);
+
+        //Every guard holds a clone of this Arc, and no new clone can be made
+        //while we hold the storages mtx.
+        let back_borrowed = back_buffers
+            .get(&type_id)
+            .is_some_and(|back| Arc::strong_count(&back.boxed) > 1);
+        if Arc::strong_count(&storage_box.boxed) > 1 || back_borrowed {
+            return Err(ECSError::Borrowed {
+                type_name: type_name::<T>(),
+            });
+        }
+
+        if let Some(bit) = storage_box.clone_storage::<T>().mask_bit() {
+            self.masks.clear_bit(bit);
+        }
+
+        storages_guard.remove(&type_id);
+        back_buffers.remove(&type_id);
+        maint_fns.remove(&type_id);
+        maint_fns.remove(&TypeId::of::<BackBuffer<T>>());
+        describe_fns.remove(&type_id);
+        named.retain(|_, (id, _)| *id != type_id);
+        #[cfg(feature = "serde")]
+        serde_fns.retain(|_, fns| fns.type_id != type_id);
+        self.forget_hooks(type_id);
+
+        Ok(())
+    }
+
+    ///Registers several Component types whose lifecycles are linked, i.e. they
+    ///are always added to and removed from Entities together. Pass them as a
+    ///tuple of 2 to 4 types:
+    ///
+    ///```
+    /// use ecs_it::*;
+    ///
+    /// struct Hp(u32);
+    /// struct MaxHp(u32);
+    /// impl Component for Hp {}
+    /// impl Component for MaxHp {}
+    ///
+    /// let world = world::World::new();
+    /// world.register_linked_components::<(Hp, MaxHp)>();
+    ///```
+    ///
+    ///Linked Storages are treated as a unit wherever the World touches them
+    ///all: world.maintain_ecs() takes every linked write guard at once, in
+    ///TypeId order like a Join's, and clears removed Entities from all of
+    ///them under those guards, so no other thread sees an Entity with only
+    ///some of its linked Components cleared. To create an Entity with all of
+    ///them as a unit, spawn() it with the whole tuple. on_remove() hooks of
+    ///linked types fire while every linked guard is held.
+    ///
+    /// ## Panics
+    /// Panics if any of the component types is already registered or appears
+    /// twice in the tuple, in which case none of them is registered.
+    pub fn register_linked_components<L: LinkedComponents>(&self) {
+        L::register_storages(self);
+        self.push_maintenance_fn(TypeId::of::<L>(), Box::new(L::maintain));
+    }
+
+    fn try_insert_storage<T: Component>(
+        &self,
+        priority: AccessPriority,
+        capacity: usize,
+    ) -> Result<(), ECSError> {
+        let type_id = TypeId::of::<T>();
+
+        let mut storages_guard: MutexGuard<'_, HashMap<TypeId, StorageBox>> =
+            self.storages.lock().expect(STORAGE_POISON);
+
+        if storages_guard.contains_key(&type_id) {
+            return Err(ECSError::AlreadyRegistered {
+                type_name: type_name::<T>(),
+            });
+        }
+
+        storages_guard.insert(type_id, self.new_storage_box::<T>(priority, capacity));
+
+        Ok(())
+    }
+
+    ///Builds the StorageBox for a new Storage of T; the caller inserts it
+    ///while holding the storages mtx.
+    fn new_storage_box<T: Component>(
+        &self,
+        priority: AccessPriority,
+        capacity: usize,
+    ) -> StorageBox {
+        fn shrink<T: Component>(world: &World) {
+            world.req_write_guard::<T>().shrink_to_fit();
+        }
+
+        fn clear<T: Component>(world: &World) {
+            world.req_write_guard::<T>().clear();
+        }
+
+        fn contains<T: Component>(world: &World, ent: Entity) -> bool {
+            world.req_read_guard::<T>().contains(&ent)
+        }
+
+        //Bits are never reused, even after unregister_component().
+        let bit = self.next_mask_bit.fetch_add(1, Ordering::Relaxed);
+        let mask = MaskBit::new(Arc::clone(&self.masks), (bit < MAX_MASK_BITS).then_some(bit));
+
+        StorageBox {
+            boxed: Arc::new(Storage::<T>::new(
+                priority,
+                Arc::clone(&self.tick),
+                capacity,
+                mask,
+            )),
+            shrink: shrink::<T>,
+            clear: clear::<T>,
+            contains: Some(contains::<T>),
+        }
+    }
+
+    ///Clones the Arc of the Storage of T without requesting any access to it.
+    ///## Panics
+    ///Panics, naming T, if T is unregistered.
+    fn storage<T: Component>(&self) -> Arc<Storage<T>> {
+        //The storages mtx is released before panicking, so that a bad request
+        //doesn't poison it for every other thread.
+        match self.storage_checked::<T>() {
+            Ok(storage) => storage,
+            Err(ECSError::Unregistered { .. }) => panic!(
+                "Attempted to access unregistered component storage: {}. \
+                 Did you forget to register a Component?",
+                type_name::<T>()
+            ),
+            Err(_) => panic!("{}", STORAGE_POISON),
+        }
+    }
+
+    fn storage_checked<T: Component>(&self) -> Result<Arc<Storage<T>>, ECSError> {
+        let storages_guard = self
+            .storages
+            .lock()
+            .map_err(|_| ECSError::Poisoned)?;
+
+        storages_guard
+            .get(&TypeId::of::<T>())
+            .map(|storage_box| storage_box.clone_storage())
+            .ok_or(ECSError::Unregistered {
+                type_name: type_name::<T>(),
+            })
+    }
+
+    ///Drops every Entity and every Component, leaving the World as if newly
+    ///created but with all of its Component types still registered, e.g. for
+    ///starting the next match. The next create_entity() returns index 0.
+    ///Generations start over too, so discard any Entity handles from before
+    ///the reset; they may come to refer to new Entities.
+    ///
+    ///Resources, unread events, and the change-detection tick are kept.
+    ///Storages are cleared one at a time and keep their capacity; see
+    ///shrink_storages(). Call this while no other thread is using the World,
+    ///else Entities it creates meanwhile may survive without Components.
+    pub fn reset(&self) {
+        let mut clear_fns: Vec<fn(&World)> = self
+            .storages
+            .lock()
+            .expect(STORAGE_POISON)
+            .values()
+            .map(|storage_box| storage_box.clear)
+            .collect();
+        clear_fns.extend(
+            self.back_buffers
+                .lock()
+                .expect(BACK_BUFFER_POISON)
+                .values()
+                .map(|storage_box| storage_box.clear),
+        );
+
+        for clear in clear_fns {
+            clear(self);
+        }
+
+        self.replace_entities(Entities::new());
+    }
+
+    ///Calls MutableStorageGuard::shrink_to_fit() on every Storage, one at a
+    ///time. Only worth it after the number of Components has dropped well
+    ///below its peak and is expected to stay there, e.g. on leaving a crowded
+    ///level, since Storages that grow again must reallocate. A good place for
+    ///it is right after maintain_ecs().
+    pub fn shrink_storages(&self) {
+        let mut shrink_fns: Vec<fn(&World)> = self
+            .storages
+            .lock()
+            .expect(STORAGE_POISON)
+            .values()
+            .map(|storage_box| storage_box.shrink)
+            .collect();
+        shrink_fns.extend(
+            self.back_buffers
+                .lock()
+                .expect(BACK_BUFFER_POISON)
+                .values()
+                .map(|storage_box| storage_box.shrink),
+        );
+
+        for shrink in shrink_fns {
+            shrink(self);
+        }
+    }
+
+    ///Removes every Component of the given Entities from every Storage.
+    pub(crate) fn clear_components(&self, ents: &[Entity]) {
+        let maint_fns = self
+            .maintenance_fns
+            .lock()
+            .expect(MAINTENANCE_FN_POISON);
+
+        //BTreeMap iterates in TypeId order, so Storages are always
+        //write-locked in the same deterministic order.
+        for f in maint_fns.values() {
+            f(self, ents);
+        }
+    }
+
+    fn push_maintenance_fn(&self, key: TypeId, f: MaintenanceFn) {
         let mut maint_fn_guard = self
             .maintenance_fns
             .lock()
             .expect(MAINTENANCE_FN_POISON);
 
-        maint_fn_guard.push(Box::new(maintain_storage::<T>));
+        maint_fn_guard.insert(key, f);
+    }
+
+    ///True if T has been registered (and not since unregistered), so that
+    ///add_component() and friends won't panic for want of a Storage.
+    pub fn is_registered<T: Component>(&self) -> bool {
+        self.is_registered_type(TypeId::of::<T>())
+    }
+
+    ///Like is_registered(), for callers holding only a TypeId.
+    pub fn is_registered_type(&self, id: TypeId) -> bool {
+        self.storages
+            .lock()
+            .expect(STORAGE_POISON)
+            .contains_key(&id)
+    }
+
+    ///Number of distinct Component types registered, linked ones included.
+    pub fn registered_component_types(&self) -> usize {
+        self.storages.lock().expect(STORAGE_POISON).len()
+    }
+
+    ///Number of Entities having every Component in Q, a tuple of 1 to 6 &T
+    ///such as (&Pos, &Vel). Briefly holds read guards, acquired in the same
+    ///order as a Join's; see Join::count().
+    /// ## Panics
+    /// Panics if any Component in Q is unregistered.
+    pub fn count_with<Q: JoinQuery>(&self) -> usize {
+        Join::<Q>::new(self).count()
+    }
+
+    ///Number of Components of type T; briefly blocks for a read guard.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn component_count<T: Component>(&self) -> usize {
+        self.req_read_guard::<T>().len()
+    }
+
+    ///Clones the Entity's Component of type T, if it has one. The read guard
+    ///is dropped before this returns, so the Storage is released immediately
+    ///rather than held for as long as the value is in use.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn get_cloned<T: Component + Clone>(&self, e: &Entity) -> Option<T> {
+        self.req_read_guard::<T>().get(e).cloned()
+    }
+
+    ///Clones every Component of type T into an owned, detached copy, for long
+    ///analyses (e.g. pathfinding over the whole map) that would otherwise
+    ///hold a read guard and keep writers waiting. The read guard is dropped
+    ///before this returns, so the copy is a point-in-time snapshot: it won't
+    ///reflect any writes made afterward.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn clone_storage<T: Component + Clone>(&self) -> HashMap<Entity, T> {
+        self.req_read_guard::<T>().snapshot()
+    }
+
+    ///Clones the Component of type T belonging to the lowest-indexed Entity,
+    ///for Components expected to exist on only one; see
+    ///ImmutableStorageGuard::first().
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn get_single<T: Component + Clone>(&self) -> Option<(Entity, T)> {
+        self.req_read_guard::<T>()
+            .first()
+            .map(|(e, c)| (e, c.clone()))
+    }
+
+    ///The ComponentMask holding just T, to be combined via | and compared
+    ///against entity_mask(). Each of the first 128 Component types registered
+    ///gets a bit; later ones, and re-registrations after unregistering, don't.
+    /// ## Panics
+    /// Panics if T is unregistered or was registered too late to get a bit.
+    pub fn component_mask<T: Component>(&self) -> ComponentMask {
+        match self.storage::<T>().mask_bit() {
+            Some(bit) => ComponentMask::from_bit(bit),
+            None => panic!(
+                "{} has no ComponentMask bit; only the first {} Component types \
+                 registered get one",
+                type_name::<T>(),
+                MAX_MASK_BITS
+            ),
+        }
+    }
+
+    ///The set of Component types the Entity has, among those with a bit; see
+    ///component_mask(). Masks are kept up to date by every write guard, but
+    ///one still held elsewhere may have changes in flight that aren't
+    ///reflected yet. A stale handle reports an empty mask, whichever Entity
+    ///now has its index.
+    pub fn entity_mask(&self, e: Entity) -> ComponentMask {
+        self.masks.get(&e)
+    }
+
+    ///True if the Entity has every Component type in mask, checked with one
+    ///bitwise AND rather than a lookup per Storage; see entity_mask().
+    pub fn matches(&self, e: Entity, mask: &ComponentMask) -> bool {
+        self.entity_mask(e).contains(mask)
     }
 
     ///Adds a component of type T to the passed-in entityr; replaces and returns
     ///the T that was already here, if any.
+    ///
+    ///If ent has been removed, or is a stale handle, comp is dropped rather
+    ///than added, since maintain_ecs() would never clear it, and None is
+    ///returned; see try_add_component() to tell the two cases apart.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
     pub fn add_component<T: Component>(&self, ent: Entity, comp: T) -> Option<T> {
+        let hooks = self.hooks::<T>();
         let mut storage_guard = self.req_write_guard::<T>(); //This may block.
 
-        //'Attatch' component to ent
-        let old_component = storage_guard.insert(ent, comp);
-        old_component
+        //Checked under the write guard: maintain_ecs() must take it to clear
+        //ent, so ent can't be removed and cleared between check and insert.
+        if !self.is_alive(ent) {
+            return None;
+        }
+
+        //'Attatch' component to ent; see on_insert()
+        hooks.insert(&mut storage_guard, ent, comp)
+    }
+
+    ///Like add_component(), but registers T first if it isn't already. Handy
+    ///for prototyping; prefer add_component() elsewhere, so that a forgotten
+    ///registration fails fast rather than going unnoticed.
+    pub fn add_component_lazy<T: Component>(&self, ent: Entity, comp: T) -> Option<T> {
+        //Err just means T was already registered.
+        let _ = self.try_register_component::<T>();
+        self.add_component(ent, comp)
+    }
+
+    ///Gives ent a T::default() if it has no T yet, e.g. for stat blocks that
+    ///are only materialized once something touches them. Returns true if a
+    ///Component was inserted, false if ent already had one, which is left as
+    ///it was, or if ent has been removed. The check and the insert happen
+    ///under one write guard, so two threads can't both insert.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn get_or_insert_default<T: Component + Default>(&self, ent: Entity) -> bool {
+        let hooks = self.hooks::<T>();
+        let mut storage_guard = self.req_write_guard::<T>(); //This may block.
+
+        if storage_guard.raw().contains_key(&ent) || !self.is_alive(ent) {
+            return false;
+        }
+
+        hooks.insert(&mut storage_guard, ent, T::default());
+        true
+    }
+
+    ///Like add_component(), but adds every (Entity, T) pair under a single
+    ///write guard. Components already present are replaced and dropped, as
+    ///are Components for removed Entities.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn add_components<T, I>(&self, iter: I)
+    where
+        T: Component,
+        I: IntoIterator<Item = (Entity, T)>,
+    {
+        let hooks = self.hooks::<T>();
+        let mut storage_guard = self.req_write_guard::<T>();
+
+        //As for add_component(), removed Entities' Components are dropped.
+        let iter: Vec<(Entity, T)> = {
+            let entities = self.lock_entities();
+            iter.into_iter()
+                .filter(|(ent, _)| entities.is_alive(ent))
+                .collect()
+        };
+
+        if hooks.is_empty() {
+            storage_guard.insert_many(iter);
+        } else {
+            for (ent, comp) in iter {
+                hooks.insert(&mut storage_guard, ent, comp);
+            }
+        }
+    }
+
+    ///Creates one Entity per Component yielded by iter and attaches it, e.g.
+    ///for data-driven level loading; the bulk counterpart of EntityBuilder.
+    ///Returns the new Entities in the same order as iter. Every Entity is
+    ///created under a single lock of the Entities list, and every Component
+    ///added under a single write guard.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T, in which case
+    /// no Entity is created, or if the World's Entity limit would be exceeded.
+    pub fn spawn_batch<T, I>(&self, iter: I) -> Vec<Entity>
+    where
+        T: Component,
+        I: IntoIterator<Item = T>,
+    {
+        let comps: Vec<T> = iter.into_iter().collect();
+        let hooks = self.hooks::<T>();
+        //Taken first, so an unregistered T panics before creating anything.
+        let mut storage_guard = self.req_write_guard::<T>();
+        let ents = self.create_entities(comps.len());
+
+        if hooks.is_empty() {
+            storage_guard.insert_many(ents.iter().copied().zip(comps));
+        } else {
+            for (ent, comp) in ents.iter().copied().zip(comps) {
+                hooks.insert(&mut storage_guard, ent, comp);
+            }
+        }
+
+        ents
     }
 
     ///Removes the component of the type T from this entity and returns it.
     ///If this component type didn't exist on this entity, None is returned.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
     pub fn rm_component<T: Component>(&self, ent: &Entity) -> Option<T> {
+        let hooks = self.hooks::<T>();
         let mut storage_guard = self.req_write_guard::<T>(); //This may block.
-        storage_guard.remove(ent)
+        hooks.remove(&mut storage_guard, ent)
+    }
+
+    ///Non-panicking counterpart to add_component(). Returns Err, adding
+    ///nothing, if T is unregistered or the Entity isn't alive; a stale handle
+    ///would otherwise leave a Component that no living Entity can reach.
+    pub fn try_add_component<T: Component>(
+        &self,
+        ent: Entity,
+        comp: T,
+    ) -> Result<Option<T>, ECSError> {
+        let hooks = self.hooks::<T>();
+        let mut storage_guard = self.req_write_guard_checked::<T>()?;
+
+        //Checked under the write guard; see add_component().
+        if !self.is_alive(ent) {
+            return Err(ECSError::NotAlive { entity: ent });
+        }
+
+        Ok(hooks.insert(&mut storage_guard, ent, comp))
+    }
+
+    ///Non-panicking counterpart to rm_component(). Returns Err if T is
+    ///unregistered. Removed-but-unmaintained Entities are fine to pass.
+    pub fn try_rm_component<T: Component>(&self, ent: &Entity) -> Result<Option<T>, ECSError> {
+        let hooks = self.hooks::<T>();
+        Ok(hooks.remove(&mut self.req_write_guard_checked::<T>()?, ent))
+    }
+
+    ///Moves from's Component of type T onto to, under a single write guard.
+    ///Returns the Component this displaced from to, if any; that is, the
+    ///return value is never from's Component, which now belongs to to.
+    ///
+    ///If from has no T, or to has been removed, nothing changes and None is
+    ///returned; to keeps whatever it had. Moving an Entity's Component onto
+    ///itself is a no-op.
+    ///
+    ///Hooks see this as a removal from from, then an insertion onto to: the
+    ///on_remove() hooks fire for from's Component, and for any Component it
+    ///displaces, and the on_insert() hooks for to.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn move_component<T: Component>(&self, from: Entity, to: Entity) -> Option<T> {
+        if from == to {
+            return None;
+        }
+
+        let hooks = self.hooks::<T>();
+        let mut storage_guard = self.req_write_guard::<T>(); //This may block.
+
+        //Like add_component(), nothing is moved onto a removed Entity.
+        if !self.is_alive(to) {
+            return None;
+        }
+
+        let moved = hooks.remove(&mut storage_guard, &from)?;
+        hooks.insert(&mut storage_guard, to, moved)
+    }
+
+    ///Drops every Component of type T without removing any Entities.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn clear_storage<T: Component>(&self) {
+        self.req_write_guard::<T>().clear();
+    }
+
+    ///True if the Entity has a Component of type T. Briefly takes read-access
+    ///to the Storage of T, so this may block.
+    /// ## Panics
+    /// Panics if you call on an unregistered Component type, T.
+    pub fn has_component<T: Component>(&self, ent: &Entity) -> bool {
+        self.req_read_guard::<T>().contains(ent)
+    }
+
+    ///The tick which Component changes are currently being recorded at; see
+    ///ImmutableStorageGuard::iter_changed_since(). Starts at 0 and only moves
+    ///when you call advance_tick().
+    pub fn current_tick(&self) -> u64 {
+        self.tick.load(Ordering::Relaxed)
+    }
+
+    ///Moves on to the next tick and returns it. Typically called once per
+    ///game tick.
+    pub fn advance_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
     }
 
     ///Must be called every once and a while, depending on how often Entities
@@ -152,42 +1147,26 @@ impl World {
     ///you'll operate on garbage data in your Systems. This won't be a
     ///"problem" per-se, but it will result in wasted CPU cycles.
     pub fn maintain_ecs(&self) {
-        let maint_fns = self
-            .maintenance_fns
-            .lock()
-            .expect(MAINTENANCE_FN_POISON);
+        //Don't hold the Entities mtx while locking Storages, else a thread
+        //holding a StorageGuard that calls create_entity() would deadlock us.
+        let dead: Vec<Entity> = self.lock_entities().take_unmaintained();
 
-        let entities_guard = self
-            .entities
-            .lock()
-            .expect(ENTITIES_POISON);
+        if dead.is_empty() {
+            return;
+        }
 
-        let dead_ent_iter = entities_guard.dead_iter();
-        let zipped = dead_ent_iter.zip(maint_fns.iter());
+        self.clear_components(&dead);
 
-        //TODO: Verify that this zip is what I want... is each f guaranteed
-        //      to be correctly paired with its associated entity?
-        for (entity, f) in zipped {
-            f(&self, entity);
-        }
+        //Only now that no Component refers to them may these IDs be reused.
+        self.lock_entities().recycle(dead);
     }
-    
+
     ///Use to get thread-safe read-access to a single ECS Storage.
     ///## Panics
     ///Panics if you call on an unregistered Component type, T.
     pub fn req_read_guard<T: Component>(&self) -> ImmutableStorageGuard<T> {
-        let type_id = TypeId::of::<T>();
-
         //Request an ImmutableStorageGuard; blocks until read-access is allowed.
-        let storage_arc = self
-            .storages
-            .lock()
-            .expect(STORAGE_POISON)
-            .get(&type_id)
-            .unwrap_or_else(|| {
-                panic!("Attempted to request access to unregistered component storage");
-            })
-            .clone_storage();
+        let storage_arc = self.storage::<T>();
 
         ImmutableStorageGuard::new(storage_arc)
     }
@@ -198,23 +1177,13 @@ impl World {
         &self,
         ent: &Entity,
     ) -> Option<ImmutableStorageGuard<T>> {
-        let type_id = TypeId::of::<T>();
-
         //Request an ImmutableStorageGuard; blocks until read-access is allowed.
-        let storage_arc = self
-            .storages
-            .lock()
-            .expect(STORAGE_POISON)
-            .get(&type_id)
-            .unwrap_or_else(|| {
-                panic!("Attempted to request access to uninitialized component storage");
-            })
-            .clone_storage();
+        let storage_arc = self.storage::<T>();
 
         {
             let guard = ImmutableStorageGuard::new(storage_arc);
 
-            if guard.get(ent).is_some() {
+            if guard.contains(ent) {
                 return Some(guard);
             }
         }
@@ -222,21 +1191,205 @@ impl World {
         None
     }
 
+    ///Runs f with read-access to the Storage of T, releasing it as soon as f
+    ///returns; an alternative to scoping a req_read_guard() in a block.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn with_read<T: Component, R>(
+        &self,
+        f: impl FnOnce(&ImmutableStorageGuard<T>) -> R,
+    ) -> R {
+        f(&self.req_read_guard::<T>())
+    }
+
+    ///Runs f with write-access to the Storage of T, releasing it as soon as f
+    ///returns; an alternative to scoping a req_write_guard() in a block.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn with_write<T: Component, R>(
+        &self,
+        f: impl FnOnce(&mut MutableStorageGuard<T>) -> R,
+    ) -> R {
+        f(&mut self.req_write_guard::<T>())
+    }
+
+    ///Calls f for every Component of type T, paired with its Entity, in
+    ///arbitrary order, under a read guard that is dropped before returning.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn for_each<T: Component>(&self, mut f: impl FnMut(Entity, &T)) {
+        for (e, c) in self.req_read_guard::<T>().iter_entities() {
+            f(e, c);
+        }
+    }
+
+    ///Like for_each(), but with &mut access under a write guard. Every
+    ///Component counts as changed for iter_changed_since().
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn for_each_mut<T: Component>(&self, mut f: impl FnMut(Entity, &mut T)) {
+        for (e, c) in self.req_write_guard::<T>().iter_entities_mut() {
+            f(e, c);
+        }
+    }
+
+    ///Calls f for every Entity having both an A and a B, with &mut access to
+    ///the A; e.g. the (&mut Pos, &Vel) of a movement system. Shorthand for
+    ///join!(world, &mut A, &B).for_each(f), so the guards are acquired in
+    ///TypeId order like any other Join's.
+    ///## Panics
+    ///Panics if A or B is unregistered, or if A and B are the same type.
+    pub fn join_mut_ref<A, B, F>(&self, f: F)
+    where
+        A: Component,
+        B: Component,
+        F: FnMut(Entity, &mut A, &B),
+    {
+        Join::<(&mut A, &B)>::new(self).for_each(f);
+    }
+
+    ///Runs f on the Entity's A, mutably, and its B, e.g. to update Health
+    ///from Armor, returning f's result. Returns None, without calling f, if
+    ///the Entity lacks either one. Both guards are acquired in TypeId order,
+    ///like a Join's, and dropped before this returns.
+    ///## Panics
+    ///Panics if A or B is unregistered, or if A and B are the same type.
+    pub fn with_pair_mut<A, B, R>(&self, e: Entity, f: impl FnOnce(&mut A, &B) -> R) -> Option<R>
+    where
+        A: Component,
+        B: Component,
+    {
+        let mut a_guard = None;
+        let mut b_guard = None;
+
+        let order = [(TypeId::of::<A>(), true, 0), (TypeId::of::<B>(), false, 1)];
+        for idx in acquisition_order(order) {
+            match idx {
+                0 => a_guard = Some(self.req_write_guard::<A>()),
+                _ => b_guard = Some(self.req_read_guard::<B>()),
+            }
+        }
+
+        let (mut a_guard, b_guard) = (a_guard?, b_guard?);
+        //Check for the B first, so the A isn't marked changed for nothing.
+        let b = b_guard.get(&e)?;
+
+        Some(f(a_guard.get_mut(&e)?, b))
+    }
+
+    ///Non-blocking version of req_read_guard(). Returns None instead of
+    ///sleeping if a writer currently holds the Storage.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn try_req_read_guard<T: Component>(&self) -> Option<ImmutableStorageGuard<T>> {
+        ImmutableStorageGuard::try_new(self.storage::<T>())
+    }
+
+    ///Non-blocking version of req_write_guard(). Returns None instead of
+    ///sleeping if any reader or writer currently holds the Storage.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn try_req_write_guard<T: Component>(&self) -> Option<MutableStorageGuard<T>> {
+        MutableStorageGuard::try_new(self.storage::<T>())
+    }
+
+    ///Takes read access to the Storage of T exactly as req_read_guard() does,
+    ///blocking until it is granted, but hands back a StorageToken in place of
+    ///a guard; the access lasts until the token is passed to unlock_storage(),
+    ///rather than until something is dropped. Meant for hosts that must hold
+    ///a Storage across calls into or out of foreign code.
+    ///
+    ///Tokens aren't seen by the debug-build held-guard checks, so requesting
+    ///write access to T on a thread that holds a token for it deadlocks
+    ///rather than panicking.
+    ///# Safety
+    ///The token must be passed to unlock_storage() exactly once. Dropping or
+    ///leaking it instead leaves the Storage of T read-locked permanently:
+    ///every writer of it, including maintain_ecs(), then blocks forever.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub unsafe fn lock_storage_read<T: Component>(&self) -> StorageToken {
+        StorageToken::lock_read(self.storage::<T>())
+    }
+
+    ///Gives back the access taken by lock_storage_read(), waking any writer
+    ///waiting on it just as dropping a guard would. The token may be
+    ///unlocked from any thread, and through any World, since it knows its
+    ///own Storage.
+    pub fn unlock_storage(&self, token: StorageToken) {
+        token.unlock();
+    }
+
+    ///How often, and for how long, threads have slept waiting on the Storage
+    ///of T since it was registered; useful for spotting the Storages worth
+    ///splitting up or re-prioritizing. Requires the metrics feature.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    #[cfg(feature = "metrics")]
+    pub fn lock_stats<T: Component>(&self) -> LockStats {
+        self.storage::<T>().lock_stats()
+    }
+
+    ///(readers_waiting, writers_waiting, upgrading) for the Storage of T.
+    #[cfg(test)]
+    pub(crate) fn waiting<T: Component>(&self) -> (u16, u16, bool) {
+        self.storage::<T>().waiting()
+    }
+
+    ///Like req_read_guard(), but returns None if read-access isn't granted
+    ///within the given Duration, so a deadlocked thread can recover.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn req_read_guard_timeout<T: Component>(
+        &self,
+        dur: Duration,
+    ) -> Option<ImmutableStorageGuard<T>> {
+        ImmutableStorageGuard::new_timeout(self.storage::<T>(), dur)
+    }
+
+    ///Like req_write_guard(), but returns None if write-access isn't granted
+    ///within the given Duration, so a deadlocked thread can recover.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn req_write_guard_timeout<T: Component>(
+        &self,
+        dur: Duration,
+    ) -> Option<MutableStorageGuard<T>> {
+        MutableStorageGuard::new_timeout(self.storage::<T>(), dur)
+    }
+
+    ///Use to get read-access to a single ECS Storage that can later be
+    ///upgraded to write-access via UpgradableStorageGuard::upgrade(). Blocks
+    ///while another thread holds an UpgradableStorageGuard for the same T.
+    ///## Panics
+    ///Panics if you call on an unregistered Component type, T.
+    pub fn req_upgradable_guard<T: Component>(&self) -> UpgradableStorageGuard<T> {
+        UpgradableStorageGuard::new(self.storage::<T>())
+    }
+
+    ///Non-panicking version of req_read_guard(). Still blocks.
+    ///
+    ///Returns Err if T is unregistered or the World's storages mtx is poisoned.
+    ///Note that a panic while holding a StorageGuard does NOT poison anything;
+    ///the guard releases its access as it unwinds.
+    pub fn req_read_guard_checked<T: Component>(
+        &self,
+    ) -> Result<ImmutableStorageGuard<T>, ECSError> {
+        Ok(ImmutableStorageGuard::new(self.storage_checked::<T>()?))
+    }
+
+    ///Non-panicking version of req_write_guard(). Still blocks.
+    pub fn req_write_guard_checked<T: Component>(
+        &self,
+    ) -> Result<MutableStorageGuard<T>, ECSError> {
+        Ok(MutableStorageGuard::new(self.storage_checked::<T>()?))
+    }
+
     ///Use to get thread-safe write-access to a single ECS Storage.
     /// ## Panics
     /// Panics if you call on an unregistered Component type, T.
     pub fn req_write_guard<T: Component>(&self) -> MutableStorageGuard<T> {
-        let type_id = TypeId::of::<T>();
-
-        let storage_arc = self
-            .storages
-            .lock()
-            .expect(STORAGE_POISON)
-            .get(&type_id)
-            .unwrap_or_else(|| {
-                panic!("Attempted to request access to uninitialized component storage");
-            })
-            .clone_storage();
+        let storage_arc = self.storage::<T>();
 
         MutableStorageGuard::new(storage_arc)
     }
@@ -244,20 +1397,10 @@ impl World {
     ///Similar to req_write_guard() but returns Some(MutableStorageGuard) if
     ///the passed-in Entity has a Component of type T. Else returns None.
     pub fn req_write_guard_if<T: Component>(&self, ent: &Entity) -> Option<MutableStorageGuard<T>> {
-        let type_id = TypeId::of::<T>();
-
-        let storage_arc = self
-            .storages
-            .lock()
-            .expect(STORAGE_POISON)
-            .get(&type_id)
-            .unwrap_or_else(|| {
-                panic!("Attempted to request access to uninitialized component storage");
-            })
-            .clone_storage();
+        let storage_arc = self.storage::<T>();
 
         {
-            let guard = MutableStorageGuard::new(storage_arc);
+            let mut guard = MutableStorageGuard::new(storage_arc);
 
             if guard.get_mut(ent).is_some() {
                 return Some(guard);
@@ -266,4 +1409,124 @@ impl World {
 
         None
     }
+
+    ///Begins a FrameAccess, which holds guards over a declared set of Storages
+    ///until it is dropped. See FrameAccess for details; note that every
+    ///declared Storage is locked for the entire lifetime of the FrameAccess.
+    pub fn frame_access(&self) -> FrameAccess<'_> {
+        FrameAccess::new(self)
+    }
+}
+
+///Creates an Entity and attaches Components to it fluently. Register every
+///Component type once, up front; the builder only adds Components.
+///
+///# Example
+///```
+/// use ecs_it::{world::{EntityBuilder, World}, Component};
+///
+/// #[derive(Component)]
+/// struct Pos(i32, i32);
+/// #[derive(Component)]
+/// struct Name(&'static str);
+///
+/// let world = World::new();
+/// world.register_component::<Pos>();
+/// world.register_component::<Name>();
+///
+/// let ent = EntityBuilder::new(&world)
+///     .with(Pos(1, 2))
+///     .with(Name("goblin"))
+///     .build();
+///
+/// assert!(world.has_component::<Pos>(&ent));
+/// assert!(world.has_component::<Name>(&ent));
+///```
+pub struct EntityBuilder<'w> {
+    world: &'w World,
+    entity: Entity,
+}
+
+impl<'w> EntityBuilder<'w> {
+    ///Creates the Entity immediately; build() just hands it back.
+    pub fn new(world: &'w World) -> Self {
+        EntityBuilder {
+            world,
+            entity: world.create_entity(),
+        }
+    }
+
+    ///## Panics
+    ///Panics if T is not registered.
+    pub fn with<T: Component>(self, comp: T) -> Self {
+        self.world.add_component(self.entity, comp);
+        self
+    }
+
+    pub fn build(self) -> Entity {
+        self.entity
+    }
 }
+
+///Implemented for tuples of 2 to 4 Component types; see
+///World::register_linked_components().
+pub trait LinkedComponents: 'static {
+    #[doc(hidden)]
+    fn register_storages(world: &World);
+
+    #[doc(hidden)]
+    fn maintain(world: &World, dead: &[Entity]);
+}
+
+macro_rules! impl_linked_components {
+    ($n:literal; $($t:ident $g:ident $idx:tt),+) => {
+        impl<$($t: Component),+> LinkedComponents for ($($t,)+) {
+            fn register_storages(world: &World) {
+                let type_ids = [$(TypeId::of::<$t>()),+];
+                let mut storages_guard = world.storages.lock().expect(STORAGE_POISON);
+
+                //Every type is checked before any is inserted, so a failure
+                //leaves none of them registered. The storages mtx is released
+                //before panicking, so that it isn't poisoned.
+                let repeated = (1..$n).any(|i| type_ids[..i].contains(&type_ids[i]));
+                let registered = type_ids.iter().any(|id| storages_guard.contains_key(id));
+                if repeated || registered {
+                    drop(storages_guard);
+                    if repeated {
+                        panic!("attempted to link the same component type twice");
+                    }
+                    panic!("attempted to register the same component type twice");
+                }
+
+                $(storages_guard.insert(
+                    TypeId::of::<$t>(),
+                    world.new_storage_box::<$t>(AccessPriority::default(), 0),
+                );)+
+            }
+
+            fn maintain(world: &World, dead: &[Entity]) {
+                let hooks = ($(world.hooks::<$t>(),)+);
+
+                //Skips any of the types that has since been unregistered.
+                $(let mut $g: Option<MutableStorageGuard<$t>> = None;)+
+                let order = acquisition_order::<$n>([$((TypeId::of::<$t>(), true, $idx),)+]);
+                for idx in order {
+                    match idx {
+                        $($idx => $g = world.req_write_guard_checked::<$t>().ok(),)+
+                        _ => unreachable!(),
+                    }
+                }
+
+                for entity in dead {
+                    $(if let Some(guard) = $g.as_mut() {
+                        hooks.$idx.remove(guard, entity);
+                    })+
+                }
+            }
+        }
+    };
+}
+
+impl_linked_components!(2; A g0 0, B g1 1);
+impl_linked_components!(3; A g0 0, B g1 1, C g2 2);
+impl_linked_components!(4; A g0 0, B g1 1, C g2 2, D g3 3);
@@ -0,0 +1,5 @@
+#[test]
+fn derive_component_ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,9 @@
+use ecs_it::Component;
+use std::rc::Rc;
+
+#[derive(Component)]
+struct NotSend {
+    shared: Rc<usize>,
+}
+
+fn main() {}